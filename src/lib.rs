@@ -1,8 +1,8 @@
-//! # VAuth - Veeam Authentication Library - v2.0.0
+//! # VAuth - Veeam Authentication Library - v3.0.0
 //!
 //! _Note that this library is unofficial and not endorsed or supported by Veeam_
 //!
-//! Also note that there are breaking changes in v2.0.0, please see the changelog for more details.
+//! Also note that there are breaking changes in v3.0.0, please see the changelog for more details.
 //!
 //! This library is used to authenticate to Veeam Backup Product REST APIs.
 //! It supports authentication to Veeam Backup & Replication, Veeam Backup for Microsoft Office 365, VONE and the Veeam Cloud Backup Products (AWS, AZURE & GCP).
@@ -11,7 +11,8 @@
 //!
 //! The library uses the builder pattern to create a reqwest client with the required authentication headers.
 //!
-//! Note that the library requires the VEEAM_API_PASSWORD environmental variable to be set. This is the password that is used to authenticate to the Veeam REST API.
+//! By default the password used to authenticate to the Veeam REST API is read from the VEEAM_API_PASSWORD environmental variable,
+//! see [Credentials](#credentials) for other options.
 //!
 //! This library is not intended to be a full featured library for the Veeam REST APIs, and there is no intention to turn it into one.
 //!
@@ -19,7 +20,7 @@
 //!
 //! Login with direct use of the client.
 //!
-//! ```rust
+//! ```no_run
 //! use vauth::{VClientBuilder, VProfile, build_url, LoginResponse};
 //! use serde_json::Value;
 //! use reqwest::Client;
@@ -41,7 +42,7 @@
 //!
 //!     let address = env::var("VB365_API_ADDRESS").unwrap();
 //!
-//!     let (client, login_response) = VClientBuilder::new(&address, username)
+//!     let (client, login_response) = VClientBuilder::new(&address, &username)
 //!         .insecure()
 //!         .build(&mut profile)
 //!         .await?;
@@ -71,7 +72,7 @@
 //!
 //! ### Reusing a saved response struct.
 //!
-//! ```rust
+//! ```no_run
 //! use vauth::{Profile, VProfile, build_url, LoginResponse, build_auth_headers};
 //! use serde_json::Value;
 //! use reqwest::Client;
@@ -113,6 +114,340 @@
 //! }
 //! ```
 //!
+//! ### Refreshing a token
+//!
+//! The login response contains a refresh token which can be exchanged for a new login response
+//! without sending the password again. This is supported by all the profiles except Enterprise Manager.
+//!
+//! ```no run
+//! let mut builder = VClientBuilder::new(&address, &username);
+//! let (client, login_response) = builder.insecure().build(&mut profile).await?;
+//!
+//! // Later, before the access token expires
+//! let login_response = builder.refresh(&profile, &login_response).await?;
+//! ```
+//!
+//! ### Multi-factor authentication
+//!
+//! When MFA is enabled for an account, VBR returns an MFA challenge instead of a token. Set a callback on the builder
+//! to supply the one-time code, which is then exchanged along with the challenge's `mfa_token` for a login response.
+//!
+//! ```no run
+//! let (client, login_response) = VClientBuilder::new(&address, &username)
+//!     .mfa(|_challenge: &MfaChallenge| {
+//!         let mut code = String::new();
+//!         std::io::stdin().read_line(&mut code).map_err(|e| LogInError::OtherError(e.to_string()))?;
+//!         Ok(code.trim().to_string())
+//!     })
+//!     .build(&mut profile)
+//!     .await?;
+//! ```
+//!
+//! ### Errors
+//!
+//! When the server returns an unsuccessful status, the error body is parsed into `LogInError::ApiError`.
+//! This keeps the product's error code and message, e.g. to tell a bad password apart from a locked account,
+//! as well as the raw body.
+//!
+//! ```no run
+//! match VClientBuilder::new(&address, &username).build(&mut profile).await {
+//!     Err(LogInError::ApiError { status, body }) => {
+//!         println!("{} {:?} {:?}", status, body.error, body.error_description);
+//!     }
+//!     _ => {}
+//! }
+//! ```
+//!
+//! ### Saving tokens between runs
+//!
+//! Rather than saving the login response by hand, a `TokenStore` can be set on the builder.
+//! Tokens are saved by profile, address and username along with the time they were issued.
+//! When building, a saved token that is still valid is reused without logging in, an expired one is refreshed,
+//! and a full login is only done if neither works.
+//!
+//! ```no run
+//! let (client, login_response) = VClientBuilder::new(&address, &username)
+//!     .token_store(FileTokenStore::new("tokens.json"))
+//!     .build(&mut profile)
+//!     .await?;
+//!
+//! println!("Token expires at {:?}", login_response.expires_at());
+//! ```
+//!
+//! ### Using the self-refreshing client
+//!
+//! `build_client` returns a `VClient` which holds the reqwest client, profile, address and token.
+//! The auth headers are added to every request, the token is refreshed before it expires and a 401
+//! response causes a single re-authentication and retry. `VClient` is cheap to clone and can be shared
+//! between tokio tasks.
+//!
+//! ```no run
+//! let client = VClientBuilder::new(&address, &username)
+//!     .insecure()
+//!     .build_client(VProfile::VBR.profile_data())
+//!     .await?;
+//!
+//! let jobs: Value = client.get_json("jobs").await?;
+//!
+//! // Any reqwest request can be sent with the auth headers added
+//! let request = client.request(Method::POST, "jobs/1234/start")?;
+//! let response = client.send(request).await?;
+//! ```
+//!
+//! ### Logging out
+//!
+//! Sessions can be ended with `VClientBuilder::logout` or `VClient::logout`, which call the product's sign-out endpoint.
+//! This is supported by VBR (`POST /api/oauth2/logout`) and Enterprise Manager (`DELETE /api/logonSessions/{id}`), the other
+//! products do not have a sign-out endpoint and return `LogInError::Unsupported`.
+//!
+//! `logout_on_drop` can be set on the builder to end the session when the last clone of a `VClient` is dropped.
+//!
+//! ```no run
+//! let client = VClientBuilder::new(&address, &username)
+//!     .logout_on_drop()
+//!     .build_client(VProfile::ENTMAN.profile_data())
+//!     .await?;
+//!
+//! // Or explicitly
+//! client.logout().await?;
+//! ```
+//!
+//! ### Paging through collections
+//!
+//! `VClient::paginate` returns a `Paginator` for a collection endpoint, which streams the items across all the pages.
+//! The query parameters and response format are chosen by the profile type, e.g. `skip` and `limit` for VBR,
+//! `offset` and `limit` with `_links.next` for VB365, and `page` and `pageSize` for Enterprise Manager.
+//!
+//! ```no run
+//! use futures_util::TryStreamExt;
+//!
+//! let mut paginator = client.paginate(&client.url("jobs")?);
+//! paginator.page_size(200).concurrency(4);
+//!
+//! println!("{:?} jobs", paginator.total().await?);
+//! let jobs: Vec<Value> = paginator.items::<Value>().try_collect().await?;
+//! ```
+//!
+//! When the total is known from the first page the remaining pages are requested concurrently, up to the concurrency limit,
+//! and the items are still returned in order.
+//!
+//! ### Waiting for sessions and tasks
+//!
+//! Starting a job or restore returns a session or task resource, such as `sessions/{id}` for VBR, `RestoreSessions/{id}`
+//! for VB365 or `tasks/{id}` for Enterprise Manager. `VClient::poll` returns a `Poller` which requests it until it finishes
+//! and maps the product's status to a `SessionResult` of `Success`, `Warning` or `Failed`.
+//!
+//! ```no run
+//! let mut poller = client.poll(&client.url(&format!("sessions/{}", session_id))?);
+//! poller
+//!     .interval(Duration::from_secs(10))
+//!     .timeout(Duration::from_secs(3600))
+//!     .on_progress(|status| println!("{:?}% complete", status.progress));
+//!
+//! // Another task can stop the poller with the canceller, or the future can be dropped
+//! let canceller = poller.canceller();
+//!
+//! let status = poller.wait().await?;
+//! if status.result == Some(SessionResult::Failed) {
+//!     println!("Session failed: {:?}", status.message);
+//! }
+//! ```
+//!
+//! ## Certificates
+//!
+//! Veeam servers normally use self-signed certificates. Rather than turning off verification with `insecure`,
+//! the certificate can be pinned to the thumbprint shown in the Veeam console. The connection is then only accepted
+//! if the server's certificate matches, and a mismatch returns `LogInError::CertificatePinMismatch` with the
+//! thumbprint the server presented.
+//!
+//! ```no run
+//! let client = VClientBuilder::new(&address, &username)
+//!     .pin_certificate_sha256("5F:0B:9A:...:12")
+//!     .build_client(VProfile::VBR.profile_data())
+//!     .await?;
+//! ```
+//!
+//! `pin_certificate_sha1` accepts the SHA-1 thumbprint shown by Windows and older consoles.
+//!
+//! When there are many servers, a known servers file can be used instead, similar to SSH's `known_hosts`.
+//! The certificate is trusted the first time a server is connected to and its thumbprint is recorded,
+//! after that the connection is only accepted if the certificate matches and a changed certificate
+//! returns `LogInError::CertificateChanged`.
+//!
+//! ```no run
+//! let client = VClientBuilder::new(&address, &username)
+//!     .known_servers(KnownServers::new("/etc/vauth/known_servers"))
+//!     .build_client(VProfile::VBR.profile_data())
+//!     .await?;
+//! ```
+//!
+//! After a certificate is replaced, remove the server with `KnownServers::remove` or edit the file.
+//!
+//! Servers with certificates from an internal PKI can be trusted by adding the CA, and a client certificate can be
+//! presented to servers or reverse proxies which require mutual TLS. These apply to the token request and the returned client.
+//!
+//! ```no run
+//! let client = VClientBuilder::new(&address, &username)
+//!     .add_root_certificate("/etc/pki/corp-root.pem")
+//!     .disable_system_roots()
+//!     .identity_pkcs12("/etc/pki/vauth-client.pfx", &pfx_password)
+//!     .build_client(VProfile::VBR.profile_data())
+//!     .await?;
+//! ```
+//!
+//! `identity_pem` loads the client certificate and private key from a single PEM file instead.
+//!
+//! ## Credentials
+//!
+//! The password is read from a `CredentialProvider` each time the client logs in. The default is the
+//! `VEEAM_API_PASSWORD` environment variable, falling back to the file named in `VEEAM_API_PASSWORD_FILE`.
+//! A different provider can be set on the builder, which allows a process to log in to servers with different passwords.
+//!
+//! | Provider          | Description                                                                      |
+//! | ----------------- | -------------------------------------------------------------------------------- |
+//! | `StaticPassword`  | A password held in memory.                                                       |
+//! | `EnvPassword`     | A named environment variable, or the file named in the variable with `_FILE`.    |
+//! | `FilePassword`    | A file such as a Docker or Kubernetes secret.                                    |
+//! | `PromptPassword`  | An interactive prompt on the terminal.                                           |
+//! | `CommandPassword` | The output of an external command, e.g. a password manager.                      |
+//!
+//! ```no run
+//! let (client, login_response) = VClientBuilder::new(&address, &username)
+//!     .credentials(EnvPassword::new("VBR01_PASSWORD"))
+//!     .build(&mut profile)
+//!     .await?;
+//!
+//! let (client, login_response) = VClientBuilder::new(&address, &username)
+//!     .credentials(CommandPassword::new("pass", &["show", "veeam/vbr02"]))
+//!     .build(&mut profile)
+//!     .await?;
+//! ```
+//!
+//! Providers return the password as a `Secret`, as do closures, e.g. `|| Ok(Secret::new(vault.read("vbr01")?))`.
+//!
+//! ### Secrets
+//!
+//! Passwords and the access and refresh tokens in `LoginResponse` are held as `Secret`, which prints `[REDACTED]`
+//! with `Debug` and `Display` and is overwritten with zeros when dropped. It still serializes to the plain string, so
+//! login responses can be saved. Use `expose_secret` to get the value.
+//!
+//! ```no run
+//! println!("{:?}", login_response); // access_token: [REDACTED], ...
+//! let headers = build_auth_headers(login_response.access_token.expose_secret(), &profile)?;
+//! ```
+//!
+//! ## Proxies
+//!
+//! The `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY` environment variables are used by default. A proxy can also be set on the builder,
+//! which is used for both the token request and the returned client. HTTPS requests are tunnelled with CONNECT.
+//!
+//! ```no run
+//! let client = VClientBuilder::new(&address, &username)
+//!     .proxy("http://jump01.corp.local:3128")
+//!     .proxy_auth(&proxy_username, &proxy_password)
+//!     .no_proxy("localhost,.mgmt.corp.local")
+//!     .build_client(VProfile::VB365.profile_data())
+//!     .await?;
+//! ```
+//!
+//! `no_proxy` applies to the proxy set on the builder or the one from the environment, along with the hosts in `NO_PROXY`,
+//! and `ignore_system_proxy` stops the environment variables being used.
+//!
+//! ## Retrying requests
+//!
+//! Requests which fail with a transient error, such as a 503 or a refused connection while the Veeam services restart,
+//! can be retried with exponential backoff. By default login and refresh requests are retried, set `api_calls` to also
+//! retry requests sent with the client.
+//!
+//! ```no run
+//! let client = VClientBuilder::new(&address, &username)
+//!     .retry(RetryPolicy {
+//!         max_attempts: 5,
+//!         api_calls: true,
+//!         ..RetryPolicy::default()
+//!     })
+//!     .build_client(VProfile::VBR.profile_data())
+//!     .await?;
+//! ```
+//!
+//! If every attempt fails, such as with a refused connection or a 503 status, `LogInError::RetryError` is returned, which
+//! holds the error from each attempt. With a single attempt a retryable status is handled as it would be without retries.
+//!
+//! ## Using your own reqwest client
+//!
+//! To keep an existing connection pool, middleware or user agent, pass your own client and vauth will only do
+//! the authentication with it. The login response is returned in the same way.
+//!
+//! ```no run
+//! let (client, login_response) = VClientBuilder::new(&address, &username)
+//!     .with_client(shared_client.clone())
+//!     .build(&mut profile)
+//!     .await?;
+//!
+//! // Or start from your own builder, with the vauth settings applied on top
+//! let (client, login_response) = VClientBuilder::new(&address, &username)
+//!     .with_client_builder(|| reqwest::Client::builder().user_agent("backup-report/1.0"))
+//!     .insecure()
+//!     .build(&mut profile)
+//!     .await?;
+//! ```
+//!
+//! Certificate pins, known servers, root certificates and client identities cannot be added to an existing client,
+//! so they return `LogInError::TlsConfigError` with `with_client`, set them with `with_client_builder` instead.
+//!
+//! ## TLS backends
+//!
+//! The `native-tls` feature, which is on by default, uses the platform's TLS library and certificate store through reqwest.
+//! The `rustls-tls` feature uses rustls and the Mozilla root certificates, which does not need OpenSSL and suits static musl builds.
+//!
+//! ```no run
+//! vauth = { version = "3", default-features = false, features = ["rustls-tls"] }
+//! ```
+//!
+//! All the certificate options work with both. Connections with pinned certificates or a known servers file always use rustls,
+//! as native-tls cannot check thumbprints. With native-tls a PEM client identity must have a PKCS#8 key (`BEGIN PRIVATE KEY`).
+//!
+//! Pinning and known servers files need the `pinning` feature, which is on by default and enabled by `rustls-tls`.
+//! To build with native-tls and without rustls, turn off the default features:
+//!
+//! ```no run
+//! vauth = { version = "3", default-features = false, features = ["native-tls"] }
+//! ```
+//!
+//! ## Tracing
+//!
+//! With the `tracing` feature, spans and events are emitted for building, logging in, refreshing and logging out.
+//! They include the token URL, the auth style, response statuses and timings, and when tokens expire.
+//! Passwords, MFA codes, access tokens and refresh tokens are never recorded.
+//!
+//! ```toml
+//! vauth = { version = "3.0.0", features = ["tracing"] }
+//! ```
+//!
+//! The events are written by whichever subscriber the application installs, e.g. `tracing_subscriber::fmt::init()`.
+//!
+//! ## Testing with the mock server
+//!
+//! The `mock` feature adds `vauth::mock::MockServer`, an in-process HTTPS server that implements the token,
+//! refresh, MFA and sign-out endpoints of each profile and checks the auth headers on other requests.
+//! API endpoints can be added with `route`, and failures injected with `fail_next`, so code using the library
+//! can be tested without a Veeam server.
+//!
+//! ```no run
+//! let server = MockServer::builder(VProfile::VBR).start().await;
+//! server.route(Method::GET, "/api/v1/jobs", |_req: &MockRequest| {
+//!     MockResponse::json(StatusCode::OK, json!({ "data": [] }))
+//! });
+//!
+//! let client = VClientBuilder::new(server.address(), &server.username())
+//!     .insecure()
+//!     .credentials(StaticPassword::new(&server.password()))
+//!     .build_client(server.profile())
+//!     .await?;
+//!
+//! let jobs: Value = client.get_json("jobs").await?;
+//! ```
+//!
 //! ## Default Profiles
 //!
 //! The library has default profiles for each API which I will try to keep up to date.
@@ -134,7 +469,7 @@
 //! You can modify the defaults using the available methods before building the client.
 //!
 //! ```no run
-//! let client: Client = VClientBuilder::new(&address, username)
+//! let client: Client = VClientBuilder::new(&address, &username)
 //!     .insecure()
 //!     .port("1234".to_string())
 //!     .api_version("v2".to_string())
@@ -143,35 +478,139 @@
 //!     .await?;
 //! ```
 //!
+//! The overrides are applied to the profile, so the port and API version are used for both the token URL and the API URLs.
+//!
+//! ### Discovering the API version
+//!
+//! The default versions may be newer or older than the server supports. With `discover_api_version` the
+//! builder asks the server for its version after logging in, then uses the newest API version that
+//! both the server and this library support. The profile is updated, so later URLs and headers use it.
+//!
+//! ```no run
+//! let mut profile = VProfile::VBR.profile_data();
+//! let (client, login_response) = VClientBuilder::new(&address, &username)
+//!     .discover_api_version()
+//!     .build(&mut profile)
+//!     .await?;
+//!
+//! println!("Using X-Api-Version {:?}", profile.x_api_version);
+//! ```
+//!
+//! | Profile | Version endpoint  | Oldest supported version |
+//! | ------- | ----------------- | ------------------------ |
+//! | VBR     | `serverInfo`      | 11.0 (`1.0-rev1`)        |
+//! | VB365   | `ServiceInstance` | 7.0 (`v7`)               |
+//! | VONE    | `about`           | 12.0 (`v2.1`)            |
+//!
+//! A server older than this returns `LogInError::UnsupportedServerVersion`. Discovery is skipped if
+//! `api_version` or `x_api_version` is set, and for the other profiles.
+//!
 //! ## Creating a Custom Profile
 //!
 //! You can create a custom profile by using the Profile struct. Note that the x_api_version
-//! is now an option and can be set to None if not required.
+//! is an option and can be set to None if not required.
 //!
 //! ```no run
-//! let mut profile = Profile::new(
-//!     name: "NEW_PROFILE",
-//!     url: ":1234/api/oauth2/token",
-//!     port: "1234",
-//!     api_version: "v1",
+//! let mut profile = Profile {
+//!     profile_type: ProfileType::VBR,
+//!     name: "NEW_PROFILE".to_string(),
+//!     scheme: "https".to_string(),
+//!     port: "1234".to_string(),
+//!     base_path: "/api".to_string(),
+//!     token_path: "/api/oauth2/token".to_string(),
+//!     logout_path: Some("/api/oauth2/logout".to_string()),
+//!     api_version: "v1".to_string(),
 //!     x_api_version: Some("1.0-rev0".to_string()),
-//! );
+//!     auth_style: AuthStyle::OAuth,
+//! };
 //! ```
 //!
 //! The Profile struct has the following fields:
 //!
 //! | Field         | Description                                                                                          |
 //! | ------------- | ---------------------------------------------------------------------------------------------------- |
+//! | profile_type  | The type of the profile, this is used to build the auth headers.                                     |
 //! | name          | The name of the profile. This is used to identify the profile.                                       |
-//! | url           | The URL used to authenticate which must have the colon at the start                                  |
-//! | api_version   | The API version, this is used to construct the URLs e.g. http://address:port/api/API_VERSION/...     |
+//! | scheme        | The URL scheme, normally https.                                                                      |
+//! | port          | The port, this can be empty to use the default port for the scheme.                                  |
+//! | base_path     | The path the API version and endpoint are added to e.g. https://address:port/BASE_PATH/API_VERSION/...|
+//! | token_path    | The path of the token endpoint, `{api_version}` is replaced with the API version.                    |
+//! | logout_path   | The optional path of the sign-out endpoint, `{session_id}` is replaced with the access token.        |
+//! | api_version   | The API version, this is used to construct the URLs e.g. https://address:port/api/API_VERSION/...    |
 //! | x_api_version | This is the X-API-Version header value.                                                              |
+//! | auth_style    | `OAuth` for a token endpoint and bearer token, or `Session` for Enterprise Manager style sessions.   |
 //!
 //! This can then be passed to the build method.
 //!
+//! `Profile::new` still accepts the old url form, which starts with the port, e.g. `:1234/api/oauth2/token`,
+//! and profiles saved with the `url` field can still be deserialized.
+//!
+//! ## Profile files
+//!
+//! Profiles can be loaded from a JSON file, or a TOML or YAML file with the `toml` or `yaml` feature, so ports,
+//! paths and versions can be changed without a code change. A profile named after a built-in profile overrides
+//! its defaults, `extends` starts a new profile from a built-in one, and other profiles set every field.
+//! Built-in names are not case sensitive, so `[profiles.vbr]` also overrides `VBR`.
+//!
+//! ```toml
+//! [profiles.VBR]
+//! port = 9420
+//!
+//! [profiles.vbr-lab]
+//! extends = "VBR"
+//! port = 443
+//! base_path = "/vbr/api"
+//! token_path = "/vbr/api/oauth2/token"
+//! ```
+//!
+//! ```no run
+//! let profiles = ProfileFile::load("profiles.toml")?;
+//! let mut profile = profiles.profile("vbr-lab")?;
+//! ```
+//!
+//! `ProfileFile::profile` falls back to the built-in profiles, so `profiles.profile("VONE")` works whether or not
+//! the file overrides it. Each profile is checked when the file is loaded, and `LogInError::ProfileFileError`
+//! names the file, the profile and the problem.
+//!
+//! ## Contexts
+//!
+//! A context file names each server with its address, profile, username, password source, TLS settings and
+//! token cache, like a kubeconfig file, so scripts do not need their own environment variables for every server.
+//! The default file is `config.toml`, `config.yaml`, `config.yml` or `config.json` in `~/.config/vauth`, whichever exists,
+//! or the path in the `VAUTH_CONFIG` environment variable. TOML and YAML files need the `toml` or `yaml` feature, which
+//! are not enabled by default, so use `config.json` with the same fields if they are not enabled.
+//!
+//! ```toml
+//! current_context = "vbr-prod"
+//!
+//! [contexts.vbr-prod]
+//! address = "vbr01.corp.local"
+//! profile = "VBR"
+//! username = "corp\\svc-veeam"
+//! credentials = { env = "VBR_PROD_PASSWORD" }
+//! token_cache = "~/.cache/vauth/tokens.json"
+//! tls = { known_servers = "~/.config/vauth/known_servers" }
+//! ```
+//!
+//! ```no run
+//! let (mut builder, profile) = VClientBuilder::from_context("vbr-prod")?;
+//! let client = builder.build_client(profile).await?;
+//!
+//! // Or the current context
+//! let (mut builder, profile) = VClientBuilder::from_current_context()?;
+//! ```
+//!
+//! Passwords are never stored in the file, `credentials` is `{ env = "VAR" }`, `{ file = "path" }`,
+//! `{ command = ["program", "arg"] }` or `"prompt"`. The file can also have a `profiles` table, as in a
+//! profile file, for contexts to use. Use `ContextFile::load` to read a file from another path.
+//!
 //! ## Build URL
-//! 
-//! Note that this only works on default profiles. Custom profiles will need to implement their own URL construction logic.
+//!
+//! The address can be an IP address, hostname or FQDN. If it includes a port, e.g. `vbr01.corp.local:443`,
+//! this is used instead of the port in the profile. IPv6 addresses are bracketed in the URL and need to be
+//! bracketed in the address when a port is added, e.g. `[fd00::10]:443`.
+//!
+//! Note that this does not work on profiles with the `UNKNOWN` profile type, which is what `Profile::new` creates.
 //!
 //! The library provides a helper function to build the URL for the Veeam REST API.
 //!
@@ -188,7 +627,7 @@
 //! ```no run
 //! let endpoint = profile.build_url(&address, &"backups".to_string())?;
 //! ```
-//! 
+//!
 //! The second way is to use the utility function `build_url` which takes the address, endpoint, and profile as parameters:
 //!
 //! ```no run
@@ -220,16 +659,36 @@
 //!
 //! See Veeam's documentation for more information on the authentication process.
 
+#[cfg(not(any(feature = "native-tls", feature = "rustls-tls")))]
+compile_error!("either the native-tls or rustls-tls feature must be enabled");
+
+#[cfg(feature = "mock")]
+pub mod mock;
 pub mod models;
 pub mod utils;
 
-pub use models::{Creds, LoginResponse, Profile, VClientBuilder, VProfile};
+pub use models::{
+    Address, ApiErrorBody, AuthStyle, CertificatePin, CommandPassword, Context, ContextFile,
+    CredentialProvider, CredentialSource, Creds, EnvPassword, FilePassword, FileTokenStore,
+    KnownServers, LoginResponse, MfaChallenge, MfaCodeProvider, MfaCreds, Page, Paginator,
+    PollCanceller, Poller, Profile, ProfileFile, ProfileFormat, ProfileType, PromptPassword,
+    RefreshCreds, RetryPolicy, RetryableError, Secret, SessionResult, SessionStatus,
+    StaticPassword, ThumbprintAlgorithm, TokenKey, TokenStore, VClient, VClientBuilder, VProfile,
+};
 pub use utils::error::LogInError;
-pub use utils::{build_auth_headers, build_url, check_valid_ip};
+pub use utils::{build_auth_headers, build_url, check_valid_address, check_valid_ip};
 
 #[cfg(test)]
 mod tests {
-    use crate::{build_url, models::vprofile::VProfile};
+    use crate::{
+        build_url, check_valid_address, models::profile::ProfileType, models::vprofile::VProfile,
+        Address, ApiErrorBody, AuthStyle, CertificatePin, CommandPassword, ContextFile,
+        CredentialProvider, CredentialSource, Creds, EnvPassword, FilePassword, FileTokenStore,
+        KnownServers, LogInError, LoginResponse, Profile, ProfileFile, ProfileFormat, RetryPolicy,
+        Secret, SessionResult, SessionStatus, StaticPassword, ThumbprintAlgorithm, TokenKey,
+        TokenStore, VClientBuilder,
+    };
+    use std::time::Duration;
 
     #[test]
     fn it_works() {
@@ -254,13 +713,735 @@ mod tests {
         let _url = build_url(&address, &end_point, &profile).unwrap();
     }
 
+    #[test]
+    fn test_profile_overrides() {
+        let mut profile = VProfile::VBAWS.profile_data();
+        profile.port = String::from("443");
+        profile.api_version = String::from("v2");
+        assert_eq!(
+            profile.token_url("192.168.0.123").unwrap(),
+            "https://192.168.0.123:443/api/v2/token"
+        );
+        assert_eq!(
+            profile.build_url("192.168.0.123", "policies").unwrap(),
+            "https://192.168.0.123:443/api/v2/policies"
+        );
+    }
+
+    #[test]
+    fn test_legacy_profile() {
+        let profile = Profile::new(
+            String::from("NEW_PROFILE"),
+            String::from(":1234/api/oauth2/token"),
+            String::from(""),
+            String::from("v1"),
+            None,
+        );
+        assert_eq!(profile.port, "1234");
+        assert_eq!(profile.token_path, "/api/oauth2/token");
+
+        let json = r#"{"profile_type":"VBR","name":"VBR","url":":9419/api/oauth2/token","port":"9419","api_version":"v1","x_api_version":"1.2-rev1"}"#;
+        let profile: Profile = serde_json::from_str(json).unwrap();
+        assert_eq!(profile.scheme, "https");
+        assert_eq!(profile.token_path, "/api/oauth2/token");
+        assert_eq!(
+            profile.token_url("192.168.0.123").unwrap(),
+            "https://192.168.0.123:9419/api/oauth2/token"
+        );
+    }
+
+    #[test]
+    fn test_build_url_hostname() {
+        let profile = VProfile::VBR.profile_data();
+        let url = profile
+            .build_url(&String::from("vbr01.corp.local"), &String::from("backups"))
+            .unwrap();
+        assert_eq!(url, "https://vbr01.corp.local:9419/api/v1/backups");
+
+        let url = profile
+            .build_url(
+                &String::from("vbr01.corp.local:443"),
+                &String::from("backups"),
+            )
+            .unwrap();
+        assert_eq!(url, "https://vbr01.corp.local:443/api/v1/backups");
+
+        let token_url = profile.token_url("vbr01:443").unwrap();
+        assert_eq!(token_url, "https://vbr01:443/api/oauth2/token");
+    }
+
+    #[test]
+    fn test_build_url_ipv6() {
+        let cases = [
+            (
+                VProfile::VBR,
+                "https://[fd00::10]:9419/api/v1/jobs",
+                "https://[fd00::10]:9419/api/oauth2/token",
+            ),
+            (
+                VProfile::VB365,
+                "https://[fd00::10]:4443/v8/jobs",
+                "https://[fd00::10]:4443/v7/Token",
+            ),
+            (
+                VProfile::VBAWS,
+                "https://[fd00::10]:11005/api/v1/jobs",
+                "https://[fd00::10]:11005/api/v1/token",
+            ),
+            (
+                VProfile::VBAZURE,
+                "https://[fd00::10]/api/v8/jobs",
+                "https://[fd00::10]/api/oauth2/token",
+            ),
+            (
+                VProfile::VBGCP,
+                "https://[fd00::10]:13140/api/v1/jobs",
+                "https://[fd00::10]:13140/api/v1/token",
+            ),
+            (
+                VProfile::VONE,
+                "https://[fd00::10]:1239/api/v2.2/jobs",
+                "https://[fd00::10]:1239/api/token",
+            ),
+            (
+                VProfile::ENTMAN,
+                "https://[fd00::10]:9398/api/jobs",
+                "https://[fd00::10]:9398/api/sessionMngr/?v=latest",
+            ),
+        ];
+
+        for (v_profile, url, token_url) in cases {
+            let profile = v_profile.profile_data();
+            assert_eq!(profile.build_url("fd00::10", "jobs").unwrap(), url);
+            assert_eq!(profile.build_url("[fd00::10]", "jobs").unwrap(), url);
+            assert_eq!(profile.token_url("fd00::10").unwrap(), token_url);
+        }
+
+        let profile = VProfile::VBR.profile_data();
+        assert_eq!(
+            profile.build_url("[fd00::10]:443", "jobs").unwrap(),
+            "https://[fd00::10]:443/api/v1/jobs"
+        );
+        assert_eq!(
+            profile.build_url("fe80::1%eth0", "jobs").unwrap(),
+            "https://[fe80::1%25eth0]:9419/api/v1/jobs"
+        );
+        assert_eq!(
+            profile.token_url("[fe80::1%25eth0]:443").unwrap(),
+            "https://[fe80::1%25eth0]:443/api/oauth2/token"
+        );
+        assert!(profile.build_url("fd00::10:443", "jobs").is_ok());
+        assert!(profile.build_url("[fd00::10]443", "jobs").is_err());
+        assert!(profile.build_url("[fd00::10", "jobs").is_err());
+    }
+
+    #[test]
+    fn test_address_validation() {
+        assert!(check_valid_address("vbr01.corp.local"));
+        assert!(check_valid_address("192.168.0.123:9419"));
+        assert!(!check_valid_address("vbr01.corp.local:99999"));
+        assert!(!check_valid_address("-vbr01.corp.local"));
+        assert!(!check_valid_address("vbr 01"));
+        assert!(matches!(Address::parse(""), Err(LogInError::AddressEmpty)));
+    }
+
+    #[test]
+    fn test_credential_providers() {
+        assert_eq!(
+            StaticPassword::new("secret")
+                .password()
+                .unwrap()
+                .expose_secret(),
+            "secret"
+        );
+
+        std::env::set_var("VAUTH_TEST_PASSWORD", "from-env");
+        assert_eq!(
+            EnvPassword::new("VAUTH_TEST_PASSWORD")
+                .password()
+                .unwrap()
+                .expose_secret(),
+            "from-env"
+        );
+
+        let path = std::env::temp_dir().join("vauth_test_password");
+        std::fs::write(&path, "from-file\n").unwrap();
+        assert_eq!(
+            FilePassword::new(&path).password().unwrap().expose_secret(),
+            "from-file"
+        );
+
+        std::env::set_var("VAUTH_TEST_FILE_PASSWORD_FILE", &path);
+        assert_eq!(
+            EnvPassword::new("VAUTH_TEST_FILE_PASSWORD")
+                .password()
+                .unwrap()
+                .expose_secret(),
+            "from-file"
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(
+            EnvPassword::new("VAUTH_TEST_MISSING_PASSWORD").password(),
+            Err(LogInError::CredentialError(_))
+        ));
+
+        #[cfg(unix)]
+        assert_eq!(
+            CommandPassword::new("echo", &["from-command"])
+                .password()
+                .unwrap()
+                .expose_secret(),
+            "from-command"
+        );
+    }
+
+    #[test]
+    fn test_file_token_store() {
+        let path = std::env::temp_dir().join("vauth_test_tokens.json");
+        let _ = std::fs::remove_file(&path);
+        let store = FileTokenStore::new(&path);
+        let profile = VProfile::VBR.profile_data();
+        let key = TokenKey::new(&profile, "vbr01.corp.local", "admin");
+
+        assert!(store.load(&key).unwrap().is_none());
+
+        let login_response = LoginResponse {
+            access_token: Secret::from("access"),
+            token_type: String::from("bearer"),
+            refresh_token: Secret::from("refresh"),
+            expires_in: 900,
+            issued_at: Some(
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap()
+                    .as_secs(),
+            ),
+        };
+        store.save(&key, &login_response).unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let leftover = std::fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .filter_map(Result::ok)
+            .any(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                name.starts_with("vauth_test_tokens.json.") && name.ends_with(".tmp")
+            });
+        assert!(!leftover);
+
+        let saved = store.load(&key).unwrap().unwrap();
+        assert_eq!(saved.access_token.expose_secret(), "access");
+        assert!(!saved.is_expired(std::time::Duration::from_secs(60)));
+        assert!(saved.is_expired(std::time::Duration::from_secs(900)));
+
+        let other = TokenKey::new(&profile, "vbr02.corp.local", "admin");
+        assert!(store.load(&other).unwrap().is_none());
+
+        store.remove(&key).unwrap();
+        assert!(store.load(&key).unwrap().is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_login_response_without_issue_time() {
+        let json =
+            r#"{"access_token":"a","token_type":"bearer","refresh_token":"r","expires_in":900}"#;
+        let login_response: LoginResponse = serde_json::from_str(json).unwrap();
+        assert!(login_response.expires_at().is_none());
+        assert!(login_response.is_expired(std::time::Duration::ZERO));
+    }
+
+    #[test]
+    fn test_logout_url() {
+        let profile = VProfile::ENTMAN.profile_data();
+        assert_eq!(
+            profile
+                .logout_url("em01.corp.local", "abc123")
+                .unwrap()
+                .unwrap(),
+            "https://em01.corp.local:9398/api/logonSessions/abc123"
+        );
+
+        let profile = VProfile::VBR.profile_data();
+        assert_eq!(
+            profile
+                .logout_url("vbr01.corp.local", "token")
+                .unwrap()
+                .unwrap(),
+            "https://vbr01.corp.local:9419/api/oauth2/logout"
+        );
+
+        let profile = VProfile::VB365.profile_data();
+        assert!(profile
+            .logout_url("vb365.corp.local", "token")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_token_response_mfa_challenge() {
+        use crate::models::mfa::TokenResponse;
+
+        let json = r#"{"mfa_token":"challenge","mfa_enabled":true}"#;
+        let response: TokenResponse = serde_json::from_str(json).unwrap();
+        assert!(
+            matches!(&response, TokenResponse::Mfa(c) if c.mfa_token.expose_secret() == "challenge")
+        );
+        assert!(matches!(
+            response.into_login_response(),
+            Err(LogInError::MfaRequired)
+        ));
+
+        let json =
+            r#"{"access_token":"a","token_type":"bearer","refresh_token":"r","expires_in":900}"#;
+        let response: TokenResponse = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            response
+                .into_login_response()
+                .unwrap()
+                .access_token
+                .expose_secret(),
+            "a"
+        );
+    }
+
+    #[test]
+    fn test_api_error_body() {
+        let body = ApiErrorBody::parse(
+            r#"{"errorCode":"AccessDenied","message":"Access is denied","resourceId":null}"#,
+        );
+        assert_eq!(body.error_code.as_deref(), Some("AccessDenied"));
+        assert_eq!(body.to_string(), "Access is denied (AccessDenied)");
+
+        let body = ApiErrorBody::parse(
+            r#"{"error":"invalid_grant","error_description":"The user name or password is incorrect"}"#,
+        );
+        assert_eq!(body.error.as_deref(), Some("invalid_grant"));
+        assert_eq!(body.summary(), "The user name or password is incorrect");
+
+        let body = ApiErrorBody::parse(r#"<Error Message="Session expired" StatusCode="401"/>"#);
+        assert!(body.message.is_none());
+        assert_eq!(
+            body.summary(),
+            r#"<Error Message="Session expired" StatusCode="401"/>"#
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresh_entman_not_supported() {
+        let profile = VProfile::ENTMAN.profile_data();
+        let login_response = LoginResponse {
+            access_token: Secret::from("session"),
+            token_type: String::from(""),
+            refresh_token: Secret::from("session"),
+            expires_in: 900,
+            issued_at: None,
+        };
+        let res = VClientBuilder::new("192.168.0.123", "user")
+            .refresh(&profile, &login_response)
+            .await;
+        assert!(matches!(res, Err(LogInError::NoRefreshToken)));
+    }
+
     #[test]
     fn test_build_profile() {
         let profile = VProfile::VBR.profile_data();
         assert!(profile.name == "VBR");
         assert!(profile.port == "9419");
-        assert!(profile.url == ":9419/api/oauth2/token");
+        assert!(profile.token_path == "/api/oauth2/token");
         assert!(profile.api_version == "v1");
-        assert!(profile.x_api_version == Some("1.1-rev0".to_string()));
+        assert!(profile.x_api_version == Some("1.2-rev1".to_string()));
+    }
+
+    #[cfg(feature = "pinning")]
+    #[test]
+    fn test_certificate_pin() {
+        let pin = CertificatePin::new(
+            ThumbprintAlgorithm::Sha1,
+            "a9:99:3e:36:47:06:81:6a:ba:3e:25:71:78:50:c2:6c:9c:d0:d8:9d",
+        )
+        .unwrap();
+        assert_eq!(pin.thumbprint, "A9993E364706816ABA3E25717850C26C9CD0D89D");
+        assert!(pin.matches(b"abc"));
+        assert_eq!(
+            ThumbprintAlgorithm::Sha256.thumbprint(b"abc"),
+            "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD"
+        );
+
+        assert!(CertificatePin::new(ThumbprintAlgorithm::Sha256, "A9993E36").is_err());
+        assert!(CertificatePin::new(
+            ThumbprintAlgorithm::Sha1,
+            "Z9993E364706816ABA3E25717850C26C9CD0D89D"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_known_servers() {
+        let path = std::env::temp_dir().join("vauth_test_known_servers");
+        let _ = std::fs::remove_file(&path);
+        let known_servers = KnownServers::new(&path);
+        assert!(known_servers.get("vbr01:9419").unwrap().is_none());
+
+        let pin = CertificatePin::new(ThumbprintAlgorithm::Sha256, &"ab".repeat(32)).unwrap();
+        known_servers.add("vbr01:9419", &pin).unwrap();
+        known_servers.add("[fd00::10]:9419", &pin).unwrap();
+        assert_eq!(known_servers.get("vbr01:9419").unwrap(), Some(pin.clone()));
+        assert!(std::fs::read_to_string(&path)
+            .unwrap()
+            .contains(&format!("vbr01:9419 SHA-256 {}", "AB".repeat(32))));
+        let leftover = std::fs::read_dir(std::env::temp_dir())
+            .unwrap()
+            .filter_map(Result::ok)
+            .any(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                name.starts_with("vauth_test_known_servers.") && name.ends_with(".tmp")
+            });
+        assert!(!leftover);
+
+        known_servers.remove("vbr01:9419").unwrap();
+        assert!(known_servers.get("vbr01:9419").unwrap().is_none());
+        assert_eq!(known_servers.get("[fd00::10]:9419").unwrap(), Some(pin));
+
+        std::fs::write(&path, "vbr01:9419 MD5 0000\n").unwrap();
+        assert!(matches!(
+            known_servers.get("vbr01:9419"),
+            Err(LogInError::KnownServersError(_))
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_secs(1));
+        assert_eq!(policy.backoff(3), Duration::from_secs(2));
+        assert_eq!(policy.backoff(10), Duration::from_secs(10));
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_secs(10));
+
+        let policy = RetryPolicy {
+            multiplier: 1.0,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.backoff(5), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_secret_redaction() {
+        let login_response = LoginResponse {
+            access_token: Secret::from("access-secret"),
+            token_type: String::from("bearer"),
+            refresh_token: Secret::from("refresh-secret"),
+            expires_in: 900,
+            issued_at: None,
+        };
+        let debug = format!("{:?}", login_response);
+        assert!(debug.contains("[REDACTED]"));
+        assert!(!debug.contains("access-secret") && !debug.contains("refresh-secret"));
+        assert_eq!(login_response.access_token.to_string(), "[REDACTED]");
+
+        let json = serde_json::to_string(&login_response).unwrap();
+        assert!(json.contains(r#""access_token":"access-secret""#));
+        let saved: LoginResponse = serde_json::from_str(&json).unwrap();
+        assert_eq!(saved.refresh_token.expose_secret(), "refresh-secret");
+
+        let password = Secret::from("password-secret");
+        let creds = Creds::new("admin", &password);
+        assert!(!format!("{:?}", creds).contains("password-secret"));
+        assert_eq!(
+            serde_urlencoded::to_string(&creds).unwrap(),
+            "grant_type=password&username=admin&password=password-secret"
+        );
+
+        assert_eq!(Secret::from("same"), Secret::from("same"));
+        assert_ne!(Secret::from("same"), Secret::from("other"));
+        assert_ne!(Secret::from("same"), Secret::from("same-but-longer"));
+
+        let headers = VProfile::VBR
+            .profile_data()
+            .build_auth_headers("a")
+            .unwrap();
+        assert!(headers["Authorization"].is_sensitive());
+        assert!(!format!("{:?}", headers).contains("Bearer a"));
+        let headers = VProfile::ENTMAN
+            .profile_data()
+            .build_auth_headers("a")
+            .unwrap();
+        assert!(headers["X-RestSvcSessionId"].is_sensitive());
+    }
+
+    #[test]
+    fn test_session_status() {
+        let status = SessionStatus::parse(
+            ProfileType::VBR,
+            serde_json::json!({ "state": "Stopped", "result": { "result": "Success", "isCanceled": true } }),
+        );
+        assert_eq!(status.result, Some(SessionResult::Failed));
+
+        let status = SessionStatus::parse(
+            ProfileType::VBAWS,
+            serde_json::json!({ "status": "Succeeded", "progress": 100 }),
+        );
+        assert!(status.is_finished());
+        assert_eq!(status.result, Some(SessionResult::Success));
+        assert_eq!(status.progress, Some(100));
+
+        let status = SessionStatus::parse(
+            ProfileType::ENTMAN,
+            serde_json::json!({ "State": "Finished", "Result": { "Success": false, "Message": "Job is disabled" } }),
+        );
+        assert_eq!(status.result, Some(SessionResult::Failed));
+        assert_eq!(status.message.as_deref(), Some("Job is disabled"));
+
+        let status = SessionStatus::parse(
+            ProfileType::VB365,
+            serde_json::json!({ "status": "Running" }),
+        );
+        assert!(!status.is_finished());
+    }
+
+    #[test]
+    fn test_negotiate_api_version() {
+        let mut profile = VProfile::VBR.profile_data();
+        profile.negotiate_api_version("12.1.2.172").unwrap();
+        assert_eq!(profile.x_api_version.as_deref(), Some("1.1-rev2"));
+
+        profile.negotiate_api_version("12.0").unwrap();
+        assert_eq!(profile.x_api_version.as_deref(), Some("1.1-rev0"));
+
+        profile.negotiate_api_version("14.0.0.100").unwrap();
+        assert_eq!(profile.x_api_version.as_deref(), Some("1.3-rev0"));
+
+        let error = profile.negotiate_api_version("10.0.1.4854").unwrap_err();
+        assert!(matches!(
+            error,
+            LogInError::UnsupportedServerVersion { ref minimum, .. } if minimum == "11.0"
+        ));
+        assert!(matches!(
+            profile.negotiate_api_version("unknown"),
+            Err(LogInError::VersionDiscoveryError(_))
+        ));
+
+        let mut profile = VProfile::VB365.profile_data();
+        profile.negotiate_api_version("7.1.0.1401").unwrap();
+        assert_eq!(profile.api_version, "v7");
+
+        let mut profile = VProfile::ENTMAN.profile_data();
+        profile.negotiate_api_version("1.0").unwrap();
+        assert_eq!(profile.api_version, "");
+    }
+
+    #[test]
+    fn test_profile_file_json() {
+        let file = ProfileFile::parse(
+            r#"{
+                "profiles": {
+                    "VBR": { "port": 9420, "x_api_version": "1.1-rev2" },
+                    "vbr-lab": { "extends": "vbr", "port": "443", "base_path": "/vbr/api" },
+                    "em-proxy": {
+                        "profile_type": "ENTMAN",
+                        "port": 8443,
+                        "base_path": "/api",
+                        "token_path": "/api/sessionMngr/?v=latest"
+                    }
+                }
+            }"#,
+            ProfileFormat::Json,
+        )
+        .unwrap();
+
+        let vbr = file.profile("VBR").unwrap();
+        assert_eq!(vbr.port, "9420");
+        assert_eq!(vbr.x_api_version.as_deref(), Some("1.1-rev2"));
+        assert_eq!(vbr.token_path, "/api/oauth2/token");
+
+        let lab = file.profile("vbr-lab").unwrap();
+        assert_eq!(lab.name, "vbr-lab");
+        assert_eq!(lab.profile_type, ProfileType::VBR);
+        assert_eq!(
+            lab.build_url("vbr01", "jobs").unwrap(),
+            "https://vbr01:443/vbr/api/v1/jobs"
+        );
+
+        let em = file.profile("em-proxy").unwrap();
+        assert_eq!(em.auth_style, AuthStyle::Session);
+        assert_eq!(
+            em.token_url("em01").unwrap(),
+            "https://em01:8443/api/sessionMngr/?v=latest"
+        );
+
+        // Built-in profiles which are not in the file are still available
+        assert_eq!(file.profile("VONE").unwrap().port, "1239");
+        assert!(file.profile("missing").is_err());
+        assert_eq!(
+            file.names().collect::<Vec<_>>(),
+            ["VBR", "em-proxy", "vbr-lab"]
+        );
+    }
+
+    #[test]
+    fn test_profile_file_built_in_case() {
+        let file = ProfileFile::parse(
+            r#"{ "profiles": { "vbr": { "port": 9420 }, "Vb365": { "port": 4444 } } }"#,
+            ProfileFormat::Json,
+        )
+        .unwrap();
+        for name in ["VBR", "vbr", "Vbr"] {
+            assert_eq!(file.profile(name).unwrap().port, "9420");
+        }
+        assert_eq!(file.get("VB365").unwrap().port, "4444");
+        assert_eq!(file.profile("vb365").unwrap().name, "VB365");
+        assert_eq!(file.names().collect::<Vec<_>>(), ["VB365", "VBR"]);
+
+        let file = ProfileFile::parse(
+            r#"{ "profiles": { "VBR": { "port": 9420 } } }"#,
+            ProfileFormat::Json,
+        )
+        .unwrap();
+        assert_eq!(file.profile("vbr").unwrap().port, "9420");
+
+        let error = ProfileFile::parse(
+            r#"{ "profiles": { "VBR": { "port": 9420 }, "vbr": { "port": 443 } } }"#,
+            ProfileFormat::Json,
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("profile `vbr`: the built-in VBR profile is overridden more than once"));
+
+        let file = ContextFile::parse(
+            r#"{
+                "contexts": { "a": { "address": "vbr01", "profile": "vbr", "username": "u" } },
+                "profiles": { "VBR": { "port": 9420 } }
+            }"#,
+            ProfileFormat::Json,
+        )
+        .unwrap();
+        let (_, profile) = file.builder("a").unwrap();
+        assert_eq!(profile.port, "9420");
+    }
+
+    #[test]
+    fn test_profile_file_validation() {
+        let error = |data: &str| match ProfileFile::parse(data, ProfileFormat::Json) {
+            Err(LogInError::ProfileFileError(message)) => message,
+            result => panic!("expected a profile file error, got {:?}", result),
+        };
+
+        assert!(error(r#"{ "profiles": { "VBR": { "port": 70000 } } }"#)
+            .contains("profile `VBR`: port must be a number from 1 to 65535"));
+        assert!(error(r#"{ "profiles": { "VBR": { "scheme": "ftp" } } }"#)
+            .contains("scheme must be https or http"));
+        assert!(
+            error(r#"{ "profiles": { "VBR": { "token_path": "api/token" } } }"#)
+                .contains("token_path must start with /")
+        );
+        assert!(
+            error(r#"{ "profiles": { "lab": { "token_path": "/api/token" } } }"#)
+                .contains("profile_type is required")
+        );
+        assert!(
+            error(r#"{ "profiles": { "lab": { "profile_type": "VBR" } } }"#)
+                .contains("token_path is required")
+        );
+        assert!(error(r#"{ "profiles": { "lab": { "extends": "VBX" } } }"#)
+            .contains("extends `VBX` which is not a built-in profile"));
+        assert!(error(r#"{ "profiles": { "VBR": { "prot": 9419 } } }"#).contains("prot"));
+    }
+
+    #[cfg(all(feature = "toml", feature = "yaml"))]
+    #[test]
+    fn test_profile_file_toml_and_yaml() {
+        let dir = std::env::temp_dir().join(format!("vauth-profiles-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let toml_path = dir.join("profiles.toml");
+        std::fs::write(
+            &toml_path,
+            "[profiles.VB365]\nport = 4444\n\n[profiles.vone-lab]\nextends = \"VONE\"\napi_version = \"v2.1\"\n",
+        )
+        .unwrap();
+        let file = ProfileFile::load(&toml_path).unwrap();
+        assert_eq!(file.profile("VB365").unwrap().port, "4444");
+        assert_eq!(file.profile("vone-lab").unwrap().api_version, "v2.1");
+
+        let yaml_path = dir.join("profiles.yml");
+        std::fs::write(
+            &yaml_path,
+            "profiles:\n  VBR:\n    port: 9420\n  broken:\n    extends: VBR\n    port: abc\n",
+        )
+        .unwrap();
+        let error = ProfileFile::load(&yaml_path).unwrap_err().to_string();
+        assert!(error.contains("profiles.yml: profile `broken`: port must be a number"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_context_file() {
+        let file = ContextFile::parse(
+            r#"{
+                "current_context": "vbr",
+                "contexts": {
+                    "vbr": {
+                        "address": "vbr01.corp.local",
+                        "profile": "VBR",
+                        "username": "svc-veeam",
+                        "credentials": { "env": "VBR_PASSWORD" },
+                        "tls": { "insecure": true }
+                    },
+                    "em": {
+                        "address": "em01.corp.local:443",
+                        "profile": "em-proxy",
+                        "username": "svc-veeam",
+                        "credentials": { "command": ["pass", "show", "em01"] }
+                    }
+                },
+                "profiles": { "em-proxy": { "extends": "ENTMAN", "port": 443 } }
+            }"#,
+            ProfileFormat::Json,
+        )
+        .unwrap();
+
+        assert_eq!(file.names().collect::<Vec<_>>(), ["em", "vbr"]);
+        let em = file.context("em").unwrap();
+        assert_eq!(
+            em.credentials,
+            CredentialSource::Command(vec!["pass".into(), "show".into(), "em01".into()])
+        );
+        assert_eq!(file.profile(em).unwrap().auth_style, AuthStyle::Session);
+        assert_eq!(
+            file.context("vbr").unwrap().credentials,
+            CredentialSource::Env("VBR_PASSWORD".into())
+        );
+        let (_, profile) = file.current_builder().unwrap();
+        assert_eq!(profile.profile_type, ProfileType::VBR);
+        assert!(file.builder("missing").is_err());
+
+        let error = |data: &str| match ContextFile::parse(data, ProfileFormat::Json) {
+            Err(LogInError::ContextError(message)) => message,
+            result => panic!("expected a context error, got {:?}", result),
+        };
+        assert!(error(r#"{ "current_context": "vbr" }"#).contains("`vbr` is not defined"));
+        assert!(error(
+            r#"{ "contexts": { "a": { "address": "vbr01", "profile": "VBX", "username": "u" } } }"#
+        )
+        .contains("context `a`: profile `VBX` is not a built-in profile"));
+        assert!(error(
+            r#"{ "contexts": { "a": { "address": "vbr01", "profile": "VBR", "username": "" } } }"#
+        )
+        .contains("username cannot be empty"));
+        assert!(error(
+            r#"{ "contexts": { "a": { "address": "vbr01", "profile": "VBR", "username": "u", "password": "p" } } }"#
+        )
+        .contains("password"));
     }
 }
@@ -0,0 +1,799 @@
+//! In-process HTTPS stand-in for the Veeam REST APIs, enabled with the `mock` feature.
+//!
+//! The mock server implements the token endpoint of each `VProfile`, form encoded OAuth with
+//! the password, refresh token and MFA grants, and Enterprise Manager basic auth sessions with
+//! the `X-RestSvcSessionId` header. It also implements the sign-out endpoints and checks the
+//! auth headers on every other request, so the builder, header, and URL logic can be tested
+//! end to end without a network.
+//!
+//! ```no run
+//! let server = MockServer::builder(VProfile::VBR).start().await;
+//!
+//! server.route(Method::GET, "/api/v1/jobs", |_req: &MockRequest| {
+//!     MockResponse::json(StatusCode::OK, json!({ "data": [] }))
+//! });
+//!
+//! let client = VClientBuilder::new(server.address(), &server.username())
+//!     .insecure()
+//!     .credentials(StaticPassword::new(&server.password()))
+//!     .build_client(server.profile())
+//!     .await?;
+//! ```
+//!
+//! The server uses a certificate for `localhost` and `127.0.0.1` issued by a CA generated when it starts.
+
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    convert::Infallible,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::{body::Incoming, server::conn::http1, service::service_fn, Request};
+use hyper_util::rt::TokioIo;
+use p12_keystore::{KeyStore, KeyStoreEntry, PrivateKey, PrivateKeyChain};
+use rcgen::{
+    BasicConstraints, CertificateParams, CertifiedIssuer, DnType, ExtendedKeyUsagePurpose, IsCa,
+    KeyPair,
+};
+use reqwest::{
+    header::{HeaderMap, HeaderValue, AUTHORIZATION, CONTENT_TYPE},
+    Method, StatusCode,
+};
+use serde_json::{json, Value};
+use tokio::{net::TcpListener, task::JoinHandle};
+use tokio_rustls::{
+    rustls::{
+        self,
+        pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer},
+        server::WebPkiClientVerifier,
+    },
+    TlsAcceptor,
+};
+
+use crate::{
+    models::{
+        api_version::VersionDiscovery,
+        profile::{AuthStyle, Profile, ProfileType},
+    },
+    VProfile,
+};
+
+/// A request received by the mock server.
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method: Method,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+/// A response returned by the mock server.
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: String,
+}
+
+impl MockResponse {
+    /// A response with a JSON body.
+    pub fn json(status: StatusCode, body: Value) -> Self {
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        MockResponse {
+            status,
+            headers,
+            body: body.to_string(),
+        }
+    }
+
+    /// A response with a plain text body, or no body if it is empty.
+    pub fn text(status: StatusCode, body: &str) -> Self {
+        MockResponse {
+            status,
+            headers: HeaderMap::new(),
+            body: body.to_string(),
+        }
+    }
+}
+
+type Handler = Arc<dyn Fn(&MockRequest) -> MockResponse + Send + Sync>;
+
+/// Builder for a `MockServer`.
+pub struct MockServerBuilder {
+    profile: Profile,
+    username: String,
+    password: String,
+    mfa_code: Option<String>,
+    expires_in: i32,
+    require_client_certificate: bool,
+    server_version: Option<String>,
+}
+
+impl MockServerBuilder {
+    /// Set the username and password the server accepts, the defaults are `mock-user` and `mock-password`.
+    pub fn credentials(&mut self, username: &str, password: &str) -> &mut Self {
+        self.username = username.to_string();
+        self.password = password.to_string();
+        self
+    }
+
+    /// Require a second multi-factor step with this one-time code, VBR only.
+    pub fn mfa_code(&mut self, code: &str) -> &mut Self {
+        self.mfa_code = Some(code.to_string());
+        self
+    }
+
+    /// Set the `expires_in` returned with tokens, the default is 900 seconds.
+    pub fn expires_in(&mut self, seconds: i32) -> &mut Self {
+        self.expires_in = seconds;
+        self
+    }
+
+    /// Report this product version, e.g. `12.1.2.172`, from the product's version endpoint
+    /// (VBR `serverInfo`, VB365 `ServiceInstance` or VONE `about`), and accept any API version
+    /// the product version supports instead of only the profile's.
+    pub fn server_version(&mut self, version: &str) -> &mut Self {
+        self.server_version = Some(version.to_string());
+        self
+    }
+
+    /// Require clients to present a certificate issued by the server's CA, like a reverse proxy
+    /// using mutual TLS. See `MockServer::client_identity_pem`.
+    pub fn require_client_certificate(&mut self) -> &mut Self {
+        self.require_client_certificate = true;
+        self
+    }
+
+    /// Start the server on a random port on 127.0.0.1.
+    pub async fn start(&self) -> MockServer {
+        let (tls_config, certificates) = tls_config(self.require_client_certificate);
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("failed to bind mock server");
+        let address = format!("127.0.0.1:{}", listener.local_addr().unwrap().port());
+
+        let state = Arc::new(Mutex::new(MockState {
+            profile: self.profile.clone(),
+            username: self.username.clone(),
+            password: self.password.clone(),
+            mfa_code: self.mfa_code.clone(),
+            expires_in: self.expires_in,
+            server_version: self.server_version.clone(),
+            issued: 0,
+            access_tokens: HashSet::new(),
+            refresh_tokens: HashSet::new(),
+            mfa_tokens: HashSet::new(),
+            routes: HashMap::new(),
+            failures: VecDeque::new(),
+            requests: Vec::new(),
+            logins: 0,
+            refreshes: 0,
+            logouts: 0,
+        }));
+
+        let acceptor = TlsAcceptor::from(Arc::new(tls_config));
+        let accept_state = state.clone();
+        let task = tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                let state = accept_state.clone();
+                tokio::spawn(async move {
+                    let Ok(tls) = acceptor.accept(tcp).await else {
+                        return;
+                    };
+                    let service = service_fn(move |req| {
+                        let state = state.clone();
+                        async move { Ok::<_, Infallible>(handle(&state, req).await) }
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(tls), service)
+                        .await;
+                });
+            }
+        });
+
+        MockServer {
+            address,
+            state,
+            task,
+            certificates,
+        }
+    }
+}
+
+/// An in-process HTTPS server that behaves like the Veeam REST API for a profile.
+/// The server is stopped when it is dropped.
+pub struct MockServer {
+    address: String,
+    state: Arc<Mutex<MockState>>,
+    task: JoinHandle<()>,
+    certificates: MockCertificates,
+}
+
+impl MockServer {
+    /// Create a builder for a mock server for the profile.
+    pub fn builder(v_profile: VProfile) -> MockServerBuilder {
+        MockServerBuilder {
+            profile: v_profile.profile_data(),
+            username: String::from("mock-user"),
+            password: String::from("mock-password"),
+            mfa_code: None,
+            expires_in: 900,
+            require_client_certificate: false,
+            server_version: None,
+        }
+    }
+
+    /// The address of the server including the port, e.g. `127.0.0.1:50123`.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// The profile the server implements, use this with the server address.
+    pub fn profile(&self) -> Profile {
+        self.state().profile.clone()
+    }
+
+    /// The username the server accepts.
+    pub fn username(&self) -> String {
+        self.state().username.clone()
+    }
+
+    /// The password the server accepts.
+    pub fn password(&self) -> String {
+        self.state().password.clone()
+    }
+
+    /// The PEM encoded CA certificate that issued the server certificate.
+    pub fn ca_certificate_pem(&self) -> &str {
+        &self.certificates.ca_pem
+    }
+
+    /// The DER encoded server certificate.
+    pub fn certificate_der(&self) -> &[u8] {
+        &self.certificates.certificate_der
+    }
+
+    /// A PEM file with a client certificate issued by the server's CA and its private key,
+    /// for use with `VClientBuilder::identity_pem`.
+    pub fn client_identity_pem(&self) -> String {
+        let certificates = &self.certificates;
+        format!(
+            "{}{}",
+            certificates.client_pem,
+            certificates.client_key.serialize_pem()
+        )
+    }
+
+    /// A PKCS#12 file with the client certificate and private key, for use with
+    /// `VClientBuilder::identity_pkcs12`.
+    pub fn client_identity_pkcs12(&self, password: &str) -> Vec<u8> {
+        let certificates = &self.certificates;
+        let chain = PrivateKeyChain::new(
+            "vauth mock client",
+            PrivateKey::from_der(&certificates.client_key.serialize_der()).unwrap(),
+            [
+                p12_keystore::Certificate::from_der(&certificates.client_der).unwrap(),
+                p12_keystore::Certificate::from_der(&certificates.ca_der).unwrap(),
+            ],
+        );
+        let mut store = KeyStore::new();
+        store.add_entry("client", KeyStoreEntry::PrivateKeyChain(chain));
+        store.writer(password).write().unwrap()
+    }
+
+    /// Add a handler for an API endpoint, the path is the full path e.g. `/api/v1/jobs`.
+    /// Requests are only passed to the handler once the auth headers have been checked.
+    pub fn route(
+        &self,
+        method: Method,
+        path: &str,
+        handler: impl Fn(&MockRequest) -> MockResponse + Send + Sync + 'static,
+    ) {
+        self.state()
+            .routes
+            .insert((method, path.to_string()), Arc::new(handler));
+    }
+
+    /// Return this response for the next request, whatever it is.
+    /// Calling this more than once queues the responses in order.
+    pub fn fail_next(&self, status: StatusCode, body: Value) {
+        self.state()
+            .failures
+            .push_back(MockResponse::json(status, body));
+    }
+
+    /// Invalidate all issued access tokens, so the next API request returns a 401.
+    /// Refresh tokens are left valid.
+    pub fn revoke_access_tokens(&self) {
+        self.state().access_tokens.clear();
+    }
+
+    /// The number of password or basic auth logins.
+    pub fn login_count(&self) -> usize {
+        self.state().logins
+    }
+
+    /// The number of refresh token grants.
+    pub fn refresh_count(&self) -> usize {
+        self.state().refreshes
+    }
+
+    /// The number of sessions ended by the sign-out endpoint.
+    pub fn logout_count(&self) -> usize {
+        self.state().logouts
+    }
+
+    /// All the requests the server has received.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state().requests.clone()
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct MockState {
+    profile: Profile,
+    username: String,
+    password: String,
+    mfa_code: Option<String>,
+    expires_in: i32,
+    server_version: Option<String>,
+    issued: usize,
+    access_tokens: HashSet<String>,
+    refresh_tokens: HashSet<String>,
+    mfa_tokens: HashSet<String>,
+    routes: HashMap<(Method, String), Handler>,
+    failures: VecDeque<MockResponse>,
+    requests: Vec<MockRequest>,
+    logins: usize,
+    refreshes: usize,
+    logouts: usize,
+}
+
+impl MockState {
+    /// Returns the X-Api-Version the server requires if the header does not match it.
+    /// With a server version any API version up to the newest it supports is accepted.
+    fn expected_x_api_version(&self, x_api_version: Option<&str>) -> Option<String> {
+        let expected = self.profile.x_api_version.clone()?;
+        let discovery = VersionDiscovery::for_profile(self.profile.profile_type);
+        let supported = match (&self.server_version, discovery) {
+            (Some(server_version), Some(discovery)) => discovery
+                .supported(&self.profile, server_version)
+                .is_ok_and(|supported| {
+                    supported
+                        .iter()
+                        .any(|revision| revision.x_api_version == x_api_version)
+                }),
+            _ => x_api_version == Some(expected.as_str()),
+        };
+
+        match supported {
+            true => None,
+            false => Some(expected),
+        }
+    }
+
+    /// Responds to the product's version endpoint if there is a server version.
+    fn version_response(&self, request: &MockRequest) -> Option<MockResponse> {
+        let server_version = self.server_version.as_ref()?;
+        let discovery = VersionDiscovery::for_profile(self.profile.profile_type)?;
+        if request.method != Method::GET
+            || !request.path.ends_with(&format!("/{}", discovery.end_point))
+        {
+            return None;
+        }
+
+        let field = discovery.pointer.trim_start_matches('/');
+        Some(MockResponse::json(
+            StatusCode::OK,
+            json!({ "name": "mock", field: server_version }),
+        ))
+    }
+
+    fn issue_token(&mut self) -> MockResponse {
+        self.issued += 1;
+        let access_token = format!("mock-access-{}", self.issued);
+        let refresh_token = format!("mock-refresh-{}", self.issued);
+        self.access_tokens.insert(access_token.clone());
+        self.refresh_tokens.insert(refresh_token.clone());
+
+        MockResponse::json(
+            StatusCode::OK,
+            json!({
+                "access_token": access_token,
+                "token_type": "bearer",
+                "refresh_token": refresh_token,
+                "expires_in": self.expires_in,
+            }),
+        )
+    }
+
+    fn token_path(&self) -> String {
+        let path = self
+            .profile
+            .token_path
+            .replace("{api_version}", &self.profile.api_version);
+        match path.split_once('?') {
+            Some((path, _)) => path.to_string(),
+            None => path,
+        }
+    }
+
+    fn is_logout(&self, request: &MockRequest, token: &str) -> bool {
+        let Some(logout_path) = &self.profile.logout_path else {
+            return false;
+        };
+        let method = match self.profile.auth_style {
+            AuthStyle::Session => Method::DELETE,
+            _ => Method::POST,
+        };
+        let path = logout_path
+            .replace("{api_version}", &self.profile.api_version)
+            .replace("{session_id}", token);
+        request.method == method && request.path == path
+    }
+}
+
+async fn handle(state: &Mutex<MockState>, req: Request<Incoming>) -> hyper::Response<Full<Bytes>> {
+    let (parts, body) = req.into_parts();
+    let body = body
+        .collect()
+        .await
+        .map(|b| b.to_bytes())
+        .unwrap_or_default();
+    let request = MockRequest {
+        method: parts.method,
+        path: parts.uri.path().to_string(),
+        query: parts
+            .uri
+            .query()
+            .and_then(|q| serde_urlencoded::from_str(q).ok())
+            .unwrap_or_default(),
+        headers: parts.headers,
+        body,
+    };
+
+    let response = {
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        state.requests.push(request.clone());
+        match state.failures.pop_front() {
+            Some(failure) => Ok(failure),
+            None => respond(&mut state, &request),
+        }
+    };
+
+    // Route handlers run without the lock so they can use the server
+    let response = match response {
+        Ok(response) => response,
+        Err(handler) => handler(&request),
+    };
+
+    let mut builder = hyper::Response::builder().status(response.status);
+    for (name, value) in response.headers.iter() {
+        builder = builder.header(name, value);
+    }
+    builder.body(Full::new(Bytes::from(response.body))).unwrap()
+}
+
+fn respond(state: &mut MockState, request: &MockRequest) -> Result<MockResponse, Handler> {
+    if request.method == Method::POST && request.path == state.token_path() {
+        return Ok(match state.profile.auth_style {
+            AuthStyle::Session => basic_login(state, request),
+            _ => oauth_token(state, request),
+        });
+    }
+
+    let token = match state.profile.auth_style {
+        AuthStyle::Session => request
+            .headers
+            .get("X-RestSvcSessionId")
+            .and_then(|v| v.to_str().ok()),
+        _ => request
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer ")),
+    }
+    .unwrap_or_default()
+    .to_string();
+
+    if !state.access_tokens.contains(&token) {
+        return Ok(MockResponse::json(
+            StatusCode::UNAUTHORIZED,
+            json!({
+                "errorCode": "Unauthorized",
+                "message": "Authorization token is missing or not valid",
+                "resourceId": null,
+            }),
+        ));
+    }
+
+    let x_api_version = request
+        .headers
+        .get("X-Api-Version")
+        .and_then(|v| v.to_str().ok());
+    if let Some(expected) = state.expected_x_api_version(x_api_version) {
+        return Ok(MockResponse::json(
+            StatusCode::BAD_REQUEST,
+            json!({
+                "errorCode": "InvalidApiVersion",
+                "message": format!("The X-Api-Version header must be {}", expected),
+                "resourceId": null,
+            }),
+        ));
+    }
+
+    if let Some(response) = state.version_response(request) {
+        return Ok(response);
+    }
+
+    if state.is_logout(request, &token) {
+        state.access_tokens.remove(&token);
+        state.logouts += 1;
+        return Ok(match state.profile.auth_style {
+            AuthStyle::Session => MockResponse::text(StatusCode::NO_CONTENT, ""),
+            _ => MockResponse::text(StatusCode::OK, ""),
+        });
+    }
+
+    match state
+        .routes
+        .get(&(request.method.clone(), request.path.clone()))
+    {
+        Some(handler) => Err(handler.clone()),
+        None => Ok(MockResponse::json(
+            StatusCode::NOT_FOUND,
+            json!({
+                "errorCode": "NotFound",
+                "message": format!("{} was not found", request.path),
+                "resourceId": null,
+            }),
+        )),
+    }
+}
+
+fn oauth_error(status: StatusCode, error: &str, description: &str) -> MockResponse {
+    MockResponse::json(
+        status,
+        json!({ "error": error, "error_description": description }),
+    )
+}
+
+/// The fields of `TokenLoginSpec`, the form body of `POST /api/oauth2/token` in the VBR REST API
+/// reference: https://helpcenter.veeam.com/docs/backup/vbr_rest/reference/vbr-rest-v1-2-rev1.html#tag/Login/operation/CreateToken
+const TOKEN_LOGIN_SPEC_FIELDS: &[&str] = &[
+    "grant_type",
+    "username",
+    "password",
+    "refresh_token",
+    "code",
+    "use_short_term_refresh",
+    "vbr_token",
+    "mfa_token",
+    "mfa_code",
+];
+
+/// Returns the fields `TokenLoginSpec` requires for the grant type, or None if it is not one of the
+/// `ELoginGrantType` values. The `mfa` grant exchanges the `mfa_token` returned by the `password`
+/// grant and the one-time `mfa_code` for a token.
+fn required_fields(grant_type: &str) -> Option<&'static [&'static str]> {
+    match grant_type {
+        "password" => Some(&["username", "password"]),
+        "refresh_token" => Some(&["refresh_token"]),
+        "authorization_code" => Some(&["code"]),
+        "vbr_token" => Some(&["vbr_token"]),
+        "mfa" => Some(&["mfa_token", "mfa_code"]),
+        _ => None,
+    }
+}
+
+fn oauth_token(state: &mut MockState, request: &MockRequest) -> MockResponse {
+    let is_form = request
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/x-www-form-urlencoded"));
+    let form: HashMap<String, String> = match serde_urlencoded::from_bytes(&request.body) {
+        Ok(form) if is_form => form,
+        _ => {
+            return oauth_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_request",
+                "The body must be application/x-www-form-urlencoded",
+            )
+        }
+    };
+    if let Some(name) = form
+        .keys()
+        .find(|name| !TOKEN_LOGIN_SPEC_FIELDS.contains(&name.as_str()))
+    {
+        return oauth_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+            &format!("{} is not a TokenLoginSpec field", name),
+        );
+    }
+    let field = |name: &str| form.get(name).map(String::as_str).unwrap_or_default();
+
+    let grant_type = field("grant_type");
+    let Some(required) = required_fields(grant_type) else {
+        return oauth_error(
+            StatusCode::BAD_REQUEST,
+            "unsupported_grant_type",
+            "The grant type is not supported",
+        );
+    };
+    if let Some(name) = required.iter().find(|name| field(name).is_empty()) {
+        return oauth_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+            &format!("{} is required for the {} grant", name, grant_type),
+        );
+    }
+
+    match grant_type {
+        "password" => {
+            if field("username") != state.username || field("password") != state.password {
+                return oauth_error(
+                    StatusCode::UNAUTHORIZED,
+                    "invalid_grant",
+                    "The user name or password is incorrect",
+                );
+            }
+            state.logins += 1;
+            if state.mfa_code.is_some() && state.profile.profile_type == ProfileType::VBR {
+                state.issued += 1;
+                let mfa_token = format!("mock-mfa-{}", state.issued);
+                state.mfa_tokens.insert(mfa_token.clone());
+                return MockResponse::json(
+                    StatusCode::OK,
+                    json!({ "mfa_token": mfa_token, "mfa_enabled": true }),
+                );
+            }
+            state.issue_token()
+        }
+        "mfa" => {
+            if !state.mfa_tokens.remove(field("mfa_token"))
+                || state.mfa_code.as_deref() != Some(field("mfa_code"))
+            {
+                return oauth_error(
+                    StatusCode::UNAUTHORIZED,
+                    "invalid_grant",
+                    "The MFA code is not valid",
+                );
+            }
+            state.issue_token()
+        }
+        "refresh_token" => {
+            if !state.refresh_tokens.remove(field("refresh_token")) {
+                return oauth_error(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_grant",
+                    "The refresh token is not valid",
+                );
+            }
+            state.refreshes += 1;
+            state.issue_token()
+        }
+        _ => oauth_error(
+            StatusCode::BAD_REQUEST,
+            "unsupported_grant_type",
+            "The grant type is not supported",
+        ),
+    }
+}
+
+fn basic_login(state: &mut MockState, request: &MockRequest) -> MockResponse {
+    let expected = format!(
+        "Basic {}",
+        STANDARD.encode(format!("{}:{}", state.username, state.password))
+    );
+    if request
+        .headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        != Some(&expected)
+    {
+        return MockResponse::json(
+            StatusCode::UNAUTHORIZED,
+            json!({ "Message": "Invalid user name or password" }),
+        );
+    }
+
+    state.logins += 1;
+    state.issued += 1;
+    let session_id = format!("mock-session-{}", state.issued);
+    state.access_tokens.insert(session_id.clone());
+
+    let mut response = MockResponse::json(
+        StatusCode::CREATED,
+        json!({ "SessionId": session_id, "UserName": state.username }),
+    );
+    response.headers.insert(
+        "X-RestSvcSessionId",
+        HeaderValue::from_str(&session_id).unwrap(),
+    );
+    response
+}
+
+/// The certificates generated when the server starts.
+struct MockCertificates {
+    ca_pem: String,
+    ca_der: Vec<u8>,
+    certificate_der: Vec<u8>,
+    client_pem: String,
+    client_der: Vec<u8>,
+    client_key: KeyPair,
+}
+
+/// Creates a CA, and a server certificate for localhost and a client certificate signed by it.
+fn tls_config(require_client_certificate: bool) -> (rustls::ServerConfig, MockCertificates) {
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params
+        .distinguished_name
+        .push(DnType::CommonName, "vauth mock CA");
+    let ca = CertifiedIssuer::self_signed(ca_params, KeyPair::generate().unwrap()).unwrap();
+
+    let key = KeyPair::generate().unwrap();
+    let mut params =
+        CertificateParams::new(vec![String::from("localhost"), String::from("127.0.0.1")]).unwrap();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "localhost");
+    let cert = params.signed_by(&key, &ca).unwrap();
+
+    let client_key = KeyPair::generate().unwrap();
+    let mut client_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    client_params
+        .distinguished_name
+        .push(DnType::CommonName, "vauth mock client");
+    client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    let client_cert = client_params.signed_by(&client_key, &ca).unwrap();
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .unwrap();
+    let config = if require_client_certificate {
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add(ca.der().clone()).unwrap();
+        config.with_client_cert_verifier(
+            WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .unwrap(),
+        )
+    } else {
+        config.with_no_client_auth()
+    };
+    let config = config
+        .with_single_cert(
+            vec![cert.der().clone(), ca.der().clone()],
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key.serialize_der())),
+        )
+        .unwrap();
+
+    let certificates = MockCertificates {
+        ca_pem: ca.pem(),
+        ca_der: ca.der().to_vec(),
+        certificate_der: cert.der().to_vec(),
+        client_pem: client_cert.pem(),
+        client_der: client_cert.der().to_vec(),
+        client_key,
+    };
+    (config, certificates)
+}
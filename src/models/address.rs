@@ -0,0 +1,217 @@
+use std::{
+    fmt,
+    net::{IpAddr, Ipv6Addr},
+    str::FromStr,
+};
+
+use crate::LogInError;
+
+/// The host part of an address, either an IP address or a DNS name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Host {
+    Ip(IpAddr),
+    /// A link-local IPv6 address with a zone ID, e.g. `fe80::1%eth0`.
+    /// Note that reqwest does not currently accept zone IDs in URLs, so these can be used to
+    /// build URLs but requests to them will fail.
+    ScopedIpv6(Ipv6Addr, String),
+    Domain(String),
+}
+
+impl Host {
+    /// Returns the host formatted for use in a URL.
+    /// IPv6 addresses are wrapped in brackets and the `%` before a zone ID is
+    /// percent encoded as described in RFC 6874, e.g. `[fe80::1%25eth0]`.
+    pub fn url_host(&self) -> String {
+        match self {
+            Host::Ip(IpAddr::V6(ip)) => format!("[{}]", ip),
+            Host::ScopedIpv6(ip, zone) => format!("[{}%25{}]", ip, zone),
+            Host::Ip(ip) => ip.to_string(),
+            Host::Domain(name) => name.clone(),
+        }
+    }
+}
+
+/// Address of a Veeam server.
+/// This can be an IP address, a hostname or FQDN, optionally followed by a port
+/// e.g. `192.168.0.123`, `vbr01.corp.local` or `vbr01.corp.local:9419`.
+/// IPv6 addresses can be given with or without brackets, but must be bracketed to add a port
+/// e.g. `fd00::10`, `[fd00::10]:9419` or `[fe80::1%eth0]:9419`.
+/// When a port is given it takes precedence over the port in the profile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    pub host: Host,
+    pub port: Option<u16>,
+}
+
+impl Address {
+    /// Parses and validates an address.
+    pub fn parse(address: &str) -> Result<Self, LogInError> {
+        let address = address.trim();
+
+        if address.is_empty() {
+            return Err(LogInError::AddressEmpty);
+        }
+
+        if let Some(rest) = address.strip_prefix('[') {
+            let (host, rest) = rest
+                .split_once(']')
+                .ok_or_else(|| invalid(address, "missing closing bracket"))?;
+            let port = match rest {
+                "" => None,
+                _ => Some(
+                    rest.strip_prefix(':')
+                        .and_then(parse_port)
+                        .ok_or_else(|| invalid(address, "the port is not valid"))?,
+                ),
+            };
+            let host = parse_ipv6(host, true)
+                .ok_or_else(|| invalid(address, "not a valid IPv6 address"))?;
+            return Ok(Address { host, port });
+        }
+
+        if let Ok(ip) = address.parse::<IpAddr>() {
+            return Ok(Address {
+                host: Host::Ip(ip),
+                port: None,
+            });
+        }
+
+        if address.contains('%') {
+            let host = parse_ipv6(address, false)
+                .ok_or_else(|| invalid(address, "not a valid IPv6 address"))?;
+            return Ok(Address { host, port: None });
+        }
+
+        if address.matches(':').count() > 1 {
+            return Err(invalid(
+                address,
+                "IPv6 addresses must be in brackets when a port is given",
+            ));
+        }
+
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) => {
+                let port =
+                    parse_port(port).ok_or_else(|| invalid(address, "the port is not valid"))?;
+                (host, Some(port))
+            }
+            None => (address, None),
+        };
+
+        let host = if let Ok(ip) = host.parse::<IpAddr>() {
+            Host::Ip(ip)
+        } else {
+            validate_hostname(host).map_err(|reason| invalid(address, reason))?;
+            Host::Domain(host.trim_end_matches('.').to_ascii_lowercase())
+        };
+
+        Ok(Address { host, port })
+    }
+
+    /// Returns the host and port to use in a URL, the default port is used when the
+    /// address does not contain one. An empty default port leaves the port off.
+    pub fn authority(&self, default_port: &str) -> String {
+        let host = self.host.url_host();
+        match (self.port, default_port) {
+            (Some(port), _) => format!("{}:{}", host, port),
+            (None, "") => host,
+            (None, port) => format!("{}:{}", host, port),
+        }
+    }
+}
+
+impl FromStr for Address {
+    type Err = LogInError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Address::parse(s)
+    }
+}
+
+impl fmt::Display for Host {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Host::Ip(ip) => write!(f, "{}", ip),
+            Host::ScopedIpv6(ip, zone) => write!(f, "{}%{}", ip, zone),
+            Host::Domain(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.port, &self.host) {
+            (Some(port), Host::Ip(IpAddr::V6(_)) | Host::ScopedIpv6(..)) => {
+                write!(f, "[{}]:{}", self.host, port)
+            }
+            (Some(port), _) => write!(f, "{}:{}", self.host, port),
+            (None, _) => write!(f, "{}", self.host),
+        }
+    }
+}
+
+fn parse_port(port: &str) -> Option<u16> {
+    port.parse::<u16>().ok().filter(|p| *p != 0)
+}
+
+/// Parses an IPv6 address with an optional zone ID. Inside brackets the zone can be
+/// percent encoded as it would be in a URL, e.g. `fe80::1%25eth0`.
+fn parse_ipv6(host: &str, bracketed: bool) -> Option<Host> {
+    match host.split_once('%') {
+        Some((ip, zone)) => {
+            let zone = match zone.strip_prefix("25") {
+                Some(decoded) if bracketed && !decoded.is_empty() => decoded,
+                _ => zone,
+            };
+            if zone.is_empty()
+                || !zone
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_.".contains(c))
+            {
+                return None;
+            }
+            Some(Host::ScopedIpv6(ip.parse().ok()?, zone.to_string()))
+        }
+        None => Some(Host::Ip(IpAddr::V6(host.parse().ok()?))),
+    }
+}
+
+fn invalid(address: &str, reason: &str) -> LogInError {
+    LogInError::InvalidAddress(format!("{}: {}", address, reason))
+}
+
+/// Checks a hostname against RFC 1123, the last label must not be numeric so that
+/// partial IP addresses such as `192.168.0` are rejected.
+fn validate_hostname(host: &str) -> Result<(), &'static str> {
+    let host = host.strip_suffix('.').unwrap_or(host);
+
+    if host.is_empty() {
+        return Err("the host is empty");
+    }
+
+    if host.len() > 253 {
+        return Err("the hostname is longer than 253 characters");
+    }
+
+    for label in host.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err("each part of the hostname must be between 1 and 63 characters");
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            return Err("parts of the hostname cannot start or end with a hyphen");
+        }
+        if !label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return Err("the hostname contains invalid characters");
+        }
+    }
+
+    if host
+        .rsplit('.')
+        .next()
+        .is_some_and(|tld| tld.chars().all(|c| c.is_ascii_digit()))
+    {
+        return Err("not a valid IP address or hostname");
+    }
+
+    Ok(())
+}
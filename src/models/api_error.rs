@@ -0,0 +1,67 @@
+use reqwest::{Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::LogInError;
+
+/// Error body returned by the Veeam REST APIs.
+/// The products use different formats, VBR returns `errorCode`, `message` and `resourceId`,
+/// the OAuth token endpoints return `error` and `error_description`, and VB365 returns `message`.
+/// The raw body is kept for anything that does not match, such as Enterprise Manager XML errors.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ApiErrorBody {
+    #[serde(default, rename = "errorCode")]
+    pub error_code: Option<String>,
+    #[serde(default, alias = "Message")]
+    pub message: Option<String>,
+    #[serde(default, rename = "resourceId")]
+    pub resource_id: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub error_description: Option<String>,
+    #[serde(skip)]
+    pub raw: String,
+}
+
+impl ApiErrorBody {
+    /// Parses an error body, falling back to just the raw body if it is not JSON.
+    pub fn parse(raw: &str) -> Self {
+        let mut body = serde_json::from_str::<ApiErrorBody>(raw).unwrap_or_default();
+        body.raw = raw.to_string();
+        body
+    }
+
+    /// Returns the most descriptive message in the body.
+    pub fn summary(&self) -> &str {
+        self.error_description
+            .as_deref()
+            .or(self.message.as_deref())
+            .or(self.error_code.as_deref())
+            .or(self.error.as_deref())
+            .unwrap_or(self.raw.trim())
+    }
+}
+
+impl fmt::Display for ApiErrorBody {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.error_code, &self.error) {
+            (Some(code), _) | (None, Some(code)) if code != self.summary() => {
+                write!(f, "{} ({})", self.summary(), code)
+            }
+            _ => write!(f, "{}", self.summary()),
+        }
+    }
+}
+
+/// Reads the body of an unsuccessful response into a `LogInError::ApiError`.
+pub(crate) async fn api_error(response: Response) -> LogInError {
+    let status: StatusCode = response.status();
+    match response.text().await {
+        Ok(raw) => LogInError::ApiError {
+            status,
+            body: Box::new(ApiErrorBody::parse(&raw)),
+        },
+        Err(e) => LogInError::ReqwestError(e),
+    }
+}
@@ -0,0 +1,196 @@
+use std::cmp::Ordering;
+
+use crate::LogInError;
+
+use super::profile::{Profile, ProfileType};
+
+/// An API version and the oldest product version which supports it.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ApiRevision {
+    pub(crate) product_version: &'static str,
+    pub(crate) api_version: &'static str,
+    pub(crate) x_api_version: Option<&'static str>,
+}
+
+impl ApiRevision {
+    pub(crate) fn apply(&self, profile: &mut Profile) {
+        profile.api_version = self.api_version.to_string();
+        profile.x_api_version = self.x_api_version.map(String::from);
+    }
+}
+
+/// Where a product reports its version, and the API versions this library supports, oldest first.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct VersionDiscovery {
+    /// The end point passed to `Profile::build_url`.
+    pub(crate) end_point: &'static str,
+    /// JSON pointer to the product version in the response.
+    pub(crate) pointer: &'static str,
+    pub(crate) revisions: &'static [ApiRevision],
+}
+
+const VBR_REVISIONS: &[ApiRevision] = &[
+    ApiRevision {
+        product_version: "11.0",
+        api_version: "v1",
+        x_api_version: Some("1.0-rev1"),
+    },
+    ApiRevision {
+        product_version: "11.0.1",
+        api_version: "v1",
+        x_api_version: Some("1.0-rev2"),
+    },
+    ApiRevision {
+        product_version: "12.0",
+        api_version: "v1",
+        x_api_version: Some("1.1-rev0"),
+    },
+    ApiRevision {
+        product_version: "12.1",
+        api_version: "v1",
+        x_api_version: Some("1.1-rev2"),
+    },
+    ApiRevision {
+        product_version: "12.2",
+        api_version: "v1",
+        x_api_version: Some("1.2-rev0"),
+    },
+    ApiRevision {
+        product_version: "12.3",
+        api_version: "v1",
+        x_api_version: Some("1.2-rev1"),
+    },
+    ApiRevision {
+        product_version: "13.0",
+        api_version: "v1",
+        x_api_version: Some("1.3-rev0"),
+    },
+];
+
+const VB365_REVISIONS: &[ApiRevision] = &[
+    ApiRevision {
+        product_version: "7.0",
+        api_version: "v7",
+        x_api_version: None,
+    },
+    ApiRevision {
+        product_version: "8.0",
+        api_version: "v8",
+        x_api_version: None,
+    },
+];
+
+const VONE_REVISIONS: &[ApiRevision] = &[
+    ApiRevision {
+        product_version: "12.0",
+        api_version: "v2.1",
+        x_api_version: None,
+    },
+    ApiRevision {
+        product_version: "12.1",
+        api_version: "v2.2",
+        x_api_version: None,
+    },
+];
+
+impl VersionDiscovery {
+    /// Returns None for products without a version endpoint, and for Enterprise Manager
+    /// which always uses the latest API.
+    pub(crate) fn for_profile(profile_type: ProfileType) -> Option<Self> {
+        match profile_type {
+            ProfileType::VBR => Some(VersionDiscovery {
+                end_point: "serverInfo",
+                pointer: "/buildVersion",
+                revisions: VBR_REVISIONS,
+            }),
+            ProfileType::VB365 => Some(VersionDiscovery {
+                end_point: "ServiceInstance",
+                pointer: "/version",
+                revisions: VB365_REVISIONS,
+            }),
+            ProfileType::VONE => Some(VersionDiscovery {
+                end_point: "about",
+                pointer: "/version",
+                revisions: VONE_REVISIONS,
+            }),
+            ProfileType::VBAWS
+            | ProfileType::VBAZURE
+            | ProfileType::VBGCP
+            | ProfileType::ENTMAN
+            | ProfileType::UNKNOWN => None,
+        }
+    }
+
+    /// Sets the oldest supported API version, which every supported server accepts,
+    /// so the version endpoint can be requested before the server version is known.
+    pub(crate) fn use_oldest(&self, profile: &mut Profile) {
+        if let Some(oldest) = self.revisions.first() {
+            oldest.apply(profile);
+        }
+    }
+
+    /// Returns the API versions the server version supports, oldest first.
+    pub(crate) fn supported(
+        &self,
+        profile: &Profile,
+        server_version: &str,
+    ) -> Result<&'static [ApiRevision], LogInError> {
+        let server = parse_version(server_version).ok_or_else(|| {
+            LogInError::VersionDiscoveryError(format!(
+                "{} is not a valid version number",
+                server_version
+            ))
+        })?;
+
+        let count = self
+            .revisions
+            .iter()
+            .take_while(|revision| {
+                parse_version(revision.product_version)
+                    .is_some_and(|minimum| compare_versions(&server, &minimum).is_ge())
+            })
+            .count();
+
+        match count {
+            0 => Err(LogInError::UnsupportedServerVersion {
+                product: profile.name.clone(),
+                version: server_version.to_string(),
+                minimum: self
+                    .revisions
+                    .first()
+                    .map(|revision| revision.product_version.to_string())
+                    .unwrap_or_default(),
+            }),
+            count => Ok(&self.revisions[..count]),
+        }
+    }
+}
+
+/// Parses a dotted version such as `12.1.2.172`, ignoring any suffix after the numbers.
+fn parse_version(version: &str) -> Option<Vec<u32>> {
+    let numbers = version
+        .trim()
+        .trim_start_matches(['v', 'V'])
+        .split(|c: char| !c.is_ascii_digit() && c != '.')
+        .next()?;
+
+    numbers
+        .split('.')
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse().ok())
+        .collect::<Option<Vec<u32>>>()
+        .filter(|parts| !parts.is_empty())
+}
+
+/// Compares versions part by part, missing parts are treated as 0.
+fn compare_versions(a: &[u32], b: &[u32]) -> Ordering {
+    (0..a.len().max(b.len()))
+        .map(|i| {
+            a.get(i)
+                .copied()
+                .unwrap_or(0)
+                .cmp(&b.get(i).copied().unwrap_or(0))
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
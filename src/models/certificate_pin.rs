@@ -0,0 +1,85 @@
+use std::fmt;
+
+#[cfg(feature = "pinning")]
+use ring::digest;
+
+use crate::LogInError;
+
+/// The hash used for a certificate thumbprint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbprintAlgorithm {
+    Sha1,
+    Sha256,
+}
+
+impl ThumbprintAlgorithm {
+    /// Returns the thumbprint of a DER encoded certificate as upper case hex, the format
+    /// shown by the Veeam consoles. Requires the `pinning` feature.
+    #[cfg(feature = "pinning")]
+    pub fn thumbprint(&self, der: &[u8]) -> String {
+        let algorithm = match self {
+            ThumbprintAlgorithm::Sha1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+            ThumbprintAlgorithm::Sha256 => &digest::SHA256,
+        };
+        digest::digest(algorithm, der)
+            .as_ref()
+            .iter()
+            .map(|b| format!("{:02X}", b))
+            .collect()
+    }
+
+    fn hex_len(&self) -> usize {
+        match self {
+            ThumbprintAlgorithm::Sha1 => 40,
+            ThumbprintAlgorithm::Sha256 => 64,
+        }
+    }
+}
+
+impl fmt::Display for ThumbprintAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThumbprintAlgorithm::Sha1 => write!(f, "SHA-1"),
+            ThumbprintAlgorithm::Sha256 => write!(f, "SHA-256"),
+        }
+    }
+}
+
+/// The expected thumbprint of the server's certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CertificatePin {
+    pub algorithm: ThumbprintAlgorithm,
+    pub thumbprint: String,
+}
+
+impl CertificatePin {
+    /// Creates a pin from a hex thumbprint. Colons, spaces and case are ignored, so the
+    /// thumbprint can be copied from the Veeam console, a browser or openssl.
+    pub fn new(algorithm: ThumbprintAlgorithm, thumbprint: &str) -> Result<Self, LogInError> {
+        let normalized: String = thumbprint
+            .chars()
+            .filter(|c| !matches!(c, ':' | ' ' | '-'))
+            .collect::<String>()
+            .to_uppercase();
+
+        if normalized.len() != algorithm.hex_len()
+            || !normalized.chars().all(|c| c.is_ascii_hexdigit())
+        {
+            return Err(LogInError::InvalidCertificatePin(format!(
+                "{} is not a {} thumbprint",
+                thumbprint, algorithm
+            )));
+        }
+
+        Ok(CertificatePin {
+            algorithm,
+            thumbprint: normalized,
+        })
+    }
+
+    /// Returns true if the DER encoded certificate matches the pin. Requires the `pinning` feature.
+    #[cfg(feature = "pinning")]
+    pub fn matches(&self, der: &[u8]) -> bool {
+        self.algorithm.thumbprint(der) == self.thumbprint
+    }
+}
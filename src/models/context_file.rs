@@ -0,0 +1,336 @@
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::LogInError;
+
+use super::{
+    profile_file::{deserialize, ProfileEntry},
+    Address, CertificatePin, CommandPassword, EnvPassword, FilePassword, FileTokenStore,
+    KnownServers, Profile, ProfileFile, ProfileFormat, PromptPassword, ThumbprintAlgorithm,
+    VClientBuilder,
+};
+
+/// The environment variable which overrides the path of the default context file.
+pub const CONTEXT_FILE_VAR: &str = "VAUTH_CONFIG";
+
+/// Where the password for a context is read from. Passwords cannot be written in the file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CredentialSource {
+    /// The `VEEAM_API_PASSWORD` environment variable, see `EnvPassword`.
+    #[default]
+    Default,
+    /// An environment variable, see `EnvPassword`.
+    Env(String),
+    /// A file containing the password, see `FilePassword`.
+    File(PathBuf),
+    /// A command which prints the password and its arguments, see `CommandPassword`.
+    Command(Vec<String>),
+    /// An interactive prompt, see `PromptPassword`.
+    Prompt,
+}
+
+/// The TLS settings of a context, see the `VClientBuilder` methods with the same names.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsSettings {
+    #[serde(default)]
+    pub insecure: bool,
+    #[serde(default)]
+    pub pin_certificate_sha256: Vec<String>,
+    #[serde(default)]
+    pub pin_certificate_sha1: Vec<String>,
+    pub known_servers: Option<PathBuf>,
+    #[serde(default)]
+    pub root_certificates: Vec<PathBuf>,
+    #[serde(default)]
+    pub disable_system_roots: bool,
+    pub identity_pem: Option<PathBuf>,
+}
+
+/// A named server in a context file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Context {
+    /// The address of the server, optionally with a port.
+    pub address: String,
+    /// The name of a built-in profile, e.g. `VBR`, or a profile defined in the file.
+    pub profile: String,
+    pub username: String,
+    #[serde(default)]
+    pub credentials: CredentialSource,
+    #[serde(default)]
+    pub tls: TlsSettings,
+    /// The file tokens are saved to and reused from, see `FileTokenStore`.
+    pub token_cache: Option<PathBuf>,
+}
+
+/// The top level of a context file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ContextFileData {
+    current_context: Option<String>,
+    #[serde(default)]
+    contexts: BTreeMap<String, Context>,
+    #[serde(default)]
+    profiles: BTreeMap<String, ProfileEntry>,
+}
+
+/// Named contexts, each combining a server address, profile, username, credential source,
+/// TLS settings and token cache, like a kubeconfig file. The default file is
+/// `~/.config/vauth/config.toml`, or `config.json` which does not need the `toml` feature,
+/// see `ContextFile::default_path`.
+///
+/// ```toml
+/// current_context = "vbr-prod"
+///
+/// [contexts.vbr-prod]
+/// address = "vbr01.corp.local"
+/// profile = "VBR"
+/// username = "corp\\svc-veeam"
+/// credentials = { env = "VBR_PROD_PASSWORD" }
+/// token_cache = "~/.cache/vauth/tokens.json"
+/// tls = { known_servers = "~/.config/vauth/known_servers" }
+///
+/// [contexts.em]
+/// address = "em01.corp.local"
+/// profile = "em-proxy"
+/// username = "corp\\svc-veeam"
+/// credentials = { command = ["pass", "show", "veeam/em01"] }
+/// tls = { pin_certificate_sha256 = ["9F:86:D0:81:..."] }
+///
+/// [profiles.em-proxy]
+/// extends = "ENTMAN"
+/// port = 443
+/// ```
+///
+/// The credential source is `{ env = "VAR" }`, `{ file = "path" }`, `{ command = ["program", "arg"] }`
+/// or `"prompt"`, and `VEEAM_API_PASSWORD` is used if it is not set. The `profiles` table is
+/// the same as a `ProfileFile`. Paths can start with `~/`, and relative paths are relative to the file.
+#[derive(Debug, Clone, Default)]
+pub struct ContextFile {
+    current_context: Option<String>,
+    contexts: BTreeMap<String, Context>,
+    profiles: ProfileFile,
+    base_dir: Option<PathBuf>,
+}
+
+impl ContextFile {
+    /// Returns the path of the default context file. This is the path in the `VAUTH_CONFIG`
+    /// environment variable if it is set, otherwise the first of `config.toml`, `config.yaml`,
+    /// `config.yml` and `config.json` which exists in the `vauth` directory of `$XDG_CONFIG_HOME`,
+    /// `~/.config` or `%APPDATA%` on Windows. If none of them exist this is `config.toml`.
+    /// TOML and YAML files require the `toml` and `yaml` features.
+    pub fn default_path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os(CONTEXT_FILE_VAR).filter(|p| !p.is_empty()) {
+            return Some(PathBuf::from(path));
+        }
+
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .filter(|p| !p.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home_dir().map(|home| home.join(".config")))
+            .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?
+            .join("vauth");
+        let path = ["toml", "yaml", "yml", "json"]
+            .into_iter()
+            .map(|extension| config_dir.join("config").with_extension(extension))
+            .find(|path| path.is_file())
+            .unwrap_or_else(|| config_dir.join("config.toml"));
+        Some(path)
+    }
+
+    /// Load the default context file, see `default_path`.
+    pub fn load_default() -> Result<Self, LogInError> {
+        let path = ContextFile::default_path().ok_or_else(|| {
+            LogInError::ContextError(format!(
+                "the config directory could not be found, set {}",
+                CONTEXT_FILE_VAR
+            ))
+        })?;
+        ContextFile::load(path)
+    }
+
+    /// Load the contexts from a file, the format is chosen from the extension as for a `ProfileFile`.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, LogInError> {
+        let path = path.into();
+        let error =
+            |message: String| LogInError::ContextError(format!("{}: {}", path.display(), message));
+
+        let format = ProfileFormat::from_path(&path)
+            .ok_or_else(|| error("the extension must be json, toml, yaml or yml".to_string()))?;
+        let data = fs::read_to_string(&path).map_err(|e| error(e.to_string()))?;
+
+        let mut file = ContextFile::parse(&data, format).map_err(|e| match e {
+            LogInError::ContextError(message) | LogInError::ProfileFileError(message) => {
+                error(message)
+            }
+            e => e,
+        })?;
+        file.base_dir = path.parent().map(Path::to_path_buf);
+        Ok(file)
+    }
+
+    /// Parse the contexts from a string in the format. Relative paths are relative to the
+    /// current directory.
+    pub fn parse(data: &str, format: ProfileFormat) -> Result<Self, LogInError> {
+        let data: ContextFileData = deserialize(data, format).map_err(LogInError::ContextError)?;
+        let file = ContextFile {
+            current_context: data.current_context,
+            contexts: data.contexts,
+            profiles: ProfileFile::from_entries(data.profiles)?,
+            base_dir: None,
+        };
+
+        if let Some(current) = &file.current_context {
+            if !file.contexts.contains_key(current) {
+                return Err(LogInError::ContextError(format!(
+                    "current_context `{}` is not defined",
+                    current
+                )));
+            }
+        }
+        for (name, context) in &file.contexts {
+            file.validate(context)
+                .map_err(|e| LogInError::ContextError(format!("context `{}`: {}", name, e)))?;
+        }
+
+        Ok(file)
+    }
+
+    /// Returns the name of the current context, if the file sets one.
+    pub fn current_context(&self) -> Option<&str> {
+        self.current_context.as_deref()
+    }
+
+    /// Returns the names of the contexts.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.contexts.keys().map(String::as_str)
+    }
+
+    /// Returns the context with the name.
+    pub fn context(&self, name: &str) -> Result<&Context, LogInError> {
+        self.contexts
+            .get(name)
+            .ok_or_else(|| LogInError::ContextError(format!("context `{}` is not defined", name)))
+    }
+
+    /// Returns the profile the context uses.
+    pub fn profile(&self, context: &Context) -> Result<Profile, LogInError> {
+        self.profiles.profile(&context.profile)
+    }
+
+    /// Returns a builder configured from the context and the profile to build it with.
+    pub fn builder(&self, name: &str) -> Result<(VClientBuilder, Profile), LogInError> {
+        let context = self.context(name)?;
+        let profile = self.profile(context)?;
+
+        let mut builder = VClientBuilder::new(&context.address, &context.username);
+        match &context.credentials {
+            CredentialSource::Default => builder.credentials(EnvPassword::default()),
+            CredentialSource::Env(var) => builder.credentials(EnvPassword::new(var)),
+            CredentialSource::File(path) => builder.credentials(FilePassword::new(self.path(path))),
+            CredentialSource::Command(command) => {
+                let args: Vec<&str> = command[1..].iter().map(String::as_str).collect();
+                builder.credentials(CommandPassword::new(&command[0], &args))
+            }
+            CredentialSource::Prompt => builder.credentials(PromptPassword::new(&format!(
+                "Password for {}@{}: ",
+                context.username, context.address
+            ))),
+        };
+
+        let tls = &context.tls;
+        if tls.insecure {
+            builder.insecure();
+        }
+        for thumbprint in &tls.pin_certificate_sha256 {
+            builder.pin_certificate_sha256(thumbprint);
+        }
+        for thumbprint in &tls.pin_certificate_sha1 {
+            builder.pin_certificate_sha1(thumbprint);
+        }
+        if let Some(path) = &tls.known_servers {
+            builder.known_servers(KnownServers::new(self.path(path)));
+        }
+        for path in &tls.root_certificates {
+            builder.add_root_certificate(self.path(path));
+        }
+        if tls.disable_system_roots {
+            builder.disable_system_roots();
+        }
+        if let Some(path) = &tls.identity_pem {
+            builder.identity_pem(self.path(path));
+        }
+        if let Some(path) = &context.token_cache {
+            builder.token_store(FileTokenStore::new(self.path(path)));
+        }
+
+        Ok((builder, profile))
+    }
+
+    /// Returns a builder configured from the current context and the profile to build it with.
+    pub fn current_builder(&self) -> Result<(VClientBuilder, Profile), LogInError> {
+        let current = self
+            .current_context
+            .as_deref()
+            .ok_or_else(|| LogInError::ContextError("current_context is not set".to_string()))?;
+        self.builder(current)
+    }
+
+    /// Checks the context can be used to build a client.
+    fn validate(&self, context: &Context) -> Result<(), String> {
+        Address::parse(&context.address).map_err(|e| e.to_string())?;
+        if context.username.is_empty() {
+            return Err("username cannot be empty".to_string());
+        }
+        self.profiles.profile(&context.profile).map_err(|_| {
+            format!(
+                "profile `{}` is not a built-in profile or defined in the file",
+                context.profile
+            )
+        })?;
+        if let CredentialSource::Command(command) = &context.credentials {
+            if command.first().is_none_or(String::is_empty) {
+                return Err("the credentials command cannot be empty".to_string());
+            }
+        }
+        for (algorithm, thumbprints) in [
+            (
+                ThumbprintAlgorithm::Sha256,
+                &context.tls.pin_certificate_sha256,
+            ),
+            (ThumbprintAlgorithm::Sha1, &context.tls.pin_certificate_sha1),
+        ] {
+            for thumbprint in thumbprints {
+                CertificatePin::new(algorithm, thumbprint).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Expands `~/` and makes relative paths relative to the file.
+    fn path(&self, path: &Path) -> PathBuf {
+        if let Ok(rest) = path.strip_prefix("~") {
+            if let Some(home) = home_dir() {
+                return home.join(rest);
+            }
+        }
+        match &self.base_dir {
+            Some(base_dir) if path.is_relative() => base_dir.join(path),
+            _ => path.to_path_buf(),
+        }
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+}
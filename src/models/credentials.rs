@@ -0,0 +1,194 @@
+use std::{env, fs, path::PathBuf, process::Command};
+
+use crate::LogInError;
+
+use super::Secret;
+
+/// The environment variable the password is read from by default.
+pub const DEFAULT_PASSWORD_VAR: &str = "VEEAM_API_PASSWORD";
+
+/// Trait used by `VClientBuilder` to get the password to authenticate with.
+/// It is called every time the client needs to log in, so the password is not kept
+/// by the builder. Implement this to read the password from somewhere else, e.g. a vault.
+/// The password is returned as a `Secret` so it is zeroed once the login request has been sent.
+pub trait CredentialProvider: Send + Sync {
+    /// Returns the password to authenticate with.
+    fn password(&self) -> Result<Secret, LogInError>;
+}
+
+impl<F> CredentialProvider for F
+where
+    F: Fn() -> Result<Secret, LogInError> + Send + Sync,
+{
+    fn password(&self) -> Result<Secret, LogInError> {
+        self()
+    }
+}
+
+/// A password held in memory.
+#[derive(Debug, Clone)]
+pub struct StaticPassword {
+    password: Secret,
+}
+
+impl StaticPassword {
+    pub fn new(password: &str) -> Self {
+        StaticPassword {
+            password: Secret::from(password),
+        }
+    }
+}
+
+impl CredentialProvider for StaticPassword {
+    fn password(&self) -> Result<Secret, LogInError> {
+        Ok(self.password.clone())
+    }
+}
+
+/// A password read from an environment variable.
+/// If the variable is not set, the file named by the variable with a `_FILE` suffix is read
+/// instead, following the Docker and Kubernetes secrets convention e.g. `VEEAM_API_PASSWORD_FILE`.
+#[derive(Debug, Clone)]
+pub struct EnvPassword {
+    var: String,
+}
+
+impl EnvPassword {
+    pub fn new(var: &str) -> Self {
+        EnvPassword {
+            var: var.to_string(),
+        }
+    }
+}
+
+/// Defaults to the `VEEAM_API_PASSWORD` environment variable.
+impl Default for EnvPassword {
+    fn default() -> Self {
+        EnvPassword::new(DEFAULT_PASSWORD_VAR)
+    }
+}
+
+impl CredentialProvider for EnvPassword {
+    fn password(&self) -> Result<Secret, LogInError> {
+        if let Ok(password) = env::var(&self.var) {
+            return Ok(Secret::from(password));
+        }
+
+        let file_var = format!("{}_FILE", self.var);
+        match env::var(&file_var) {
+            Ok(path) => FilePassword::new(path).password(),
+            Err(_) => Err(LogInError::CredentialError(format!(
+                "The {} environmental variable is missing, and {} is not set",
+                self.var, file_var
+            ))),
+        }
+    }
+}
+
+/// A password read from a file, e.g. a mounted Docker or Kubernetes secret.
+/// A trailing newline is removed.
+#[derive(Debug, Clone)]
+pub struct FilePassword {
+    path: PathBuf,
+}
+
+impl FilePassword {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FilePassword { path: path.into() }
+    }
+}
+
+impl CredentialProvider for FilePassword {
+    fn password(&self) -> Result<Secret, LogInError> {
+        let contents = fs::read_to_string(&self.path).map_err(|e| {
+            LogInError::CredentialError(format!(
+                "Unable to read password file {}: {}",
+                self.path.display(),
+                e
+            ))
+        })?;
+
+        Ok(trim_newline(contents))
+    }
+}
+
+/// A password entered at an interactive prompt, the input is not echoed.
+#[derive(Debug, Clone)]
+pub struct PromptPassword {
+    prompt: String,
+}
+
+impl PromptPassword {
+    pub fn new(prompt: &str) -> Self {
+        PromptPassword {
+            prompt: prompt.to_string(),
+        }
+    }
+}
+
+impl Default for PromptPassword {
+    fn default() -> Self {
+        PromptPassword::new("Password: ")
+    }
+}
+
+impl CredentialProvider for PromptPassword {
+    fn password(&self) -> Result<Secret, LogInError> {
+        rpassword::prompt_password(&self.prompt)
+            .map(Secret::from)
+            .map_err(|e| {
+                LogInError::CredentialError(format!("Unable to read password from prompt: {}", e))
+            })
+    }
+}
+
+/// A password read from the standard output of an external command,
+/// e.g. a password manager CLI. A trailing newline is removed.
+#[derive(Debug, Clone)]
+pub struct CommandPassword {
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandPassword {
+    pub fn new(program: &str, args: &[&str]) -> Self {
+        CommandPassword {
+            program: program.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+}
+
+impl CredentialProvider for CommandPassword {
+    fn password(&self) -> Result<Secret, LogInError> {
+        let output = Command::new(&self.program)
+            .args(&self.args)
+            .output()
+            .map_err(|e| {
+                LogInError::CredentialError(format!("Unable to run {}: {}", self.program, e))
+            })?;
+
+        if !output.status.success() {
+            return Err(LogInError::CredentialError(format!(
+                "{} exited with {}",
+                self.program, output.status
+            )));
+        }
+
+        let stdout = String::from_utf8(output.stdout).map_err(|_| {
+            LogInError::CredentialError(format!("{} returned invalid UTF-8", self.program))
+        })?;
+
+        Ok(trim_newline(stdout))
+    }
+}
+
+fn trim_newline(mut value: String) -> Secret {
+    if value.ends_with('\n') {
+        value.pop();
+        if value.ends_with('\r') {
+            value.pop();
+        }
+    }
+    Secret::from(value)
+}
@@ -1,28 +1,31 @@
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
+
+use super::Secret;
 
 /// Struct representing credentials for authentication
 /// with a username and password.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize)]
 pub struct Creds<'a> {
     pub grant_type: &'static str,
     pub username: &'a str,
-    pub password: &'a str,
+    pub password: &'a Secret,
 }
 
 /// Default implementation for the `Creds` struct.
 impl<'a> Default for Creds<'a> {
     fn default() -> Self {
+        static EMPTY: Secret = Secret::empty();
         Creds {
             grant_type: "password",
             username: "",
-            password: "",
+            password: &EMPTY,
         }
     }
 }
 
 /// Implementation of methods for the `Creds` struct.
 impl<'a> Creds<'a> {
-    pub fn new(username: &'a str, password: &'a str) -> Self {
+    pub fn new(username: &'a str, password: &'a Secret) -> Self {
         Creds {
             grant_type: "password",
             username,
@@ -30,3 +33,42 @@ impl<'a> Creds<'a> {
         }
     }
 }
+
+/// Struct representing a refresh token grant, used to exchange a refresh token
+/// for a new access token without re-sending the password.
+#[derive(Debug, Serialize)]
+pub struct RefreshCreds<'a> {
+    pub grant_type: &'static str,
+    pub refresh_token: &'a Secret,
+}
+
+/// Implementation of methods for the `RefreshCreds` struct.
+impl<'a> RefreshCreds<'a> {
+    pub fn new(refresh_token: &'a Secret) -> Self {
+        RefreshCreds {
+            grant_type: "refresh_token",
+            refresh_token,
+        }
+    }
+}
+
+/// Struct representing the second step of a multi-factor login, which exchanges
+/// the MFA token and a one-time code for an access token.
+/// This is the `mfa` grant of `TokenLoginSpec` in the VBR REST API reference.
+#[derive(Debug, Serialize)]
+pub struct MfaCreds<'a> {
+    pub grant_type: &'static str,
+    pub mfa_token: &'a Secret,
+    pub mfa_code: &'a Secret,
+}
+
+/// Implementation of methods for the `MfaCreds` struct.
+impl<'a> MfaCreds<'a> {
+    pub fn new(mfa_token: &'a Secret, mfa_code: &'a Secret) -> Self {
+        MfaCreds {
+            grant_type: "mfa",
+            mfa_token,
+            mfa_code,
+        }
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{utils::files::replace_file, LogInError};
+
+use super::{CertificatePin, ThumbprintAlgorithm};
+
+/// A file of server certificate thumbprints, similar to SSH's `known_hosts`.
+/// When set on `VClientBuilder` with `known_servers`, the SHA-256 thumbprint of a server's
+/// certificate is recorded the first time it is connected to, and later connections are only
+/// accepted if the certificate matches. A changed certificate returns `LogInError::CertificateChanged`.
+///
+/// Each line is the server's address and port, the algorithm and the thumbprint, e.g.
+///
+/// ```text
+/// vbr01.corp.local:9419 SHA-256 5F0B9A...
+/// ```
+///
+/// Lines starting with `#` are ignored. Entries can be added by hand to trust a server before
+/// the first connection, and removed with `remove` when a certificate is replaced.
+#[derive(Debug)]
+pub struct KnownServers {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl KnownServers {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        KnownServers {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the recorded thumbprint for the server, e.g. `vbr01.corp.local:9419`.
+    pub fn get(&self, server: &str) -> Result<Option<CertificatePin>, LogInError> {
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(self.read()?.remove(server))
+    }
+
+    /// Records the thumbprint for the server. An existing thumbprint is not replaced, if it is
+    /// different `LogInError::CertificateChanged` is returned, so `remove` the server first.
+    pub fn add(&self, server: &str, pin: &CertificatePin) -> Result<(), LogInError> {
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut servers = self.read()?;
+        match servers.get(server) {
+            Some(recorded) if recorded == pin => Ok(()),
+            Some(recorded) => Err(LogInError::CertificateChanged {
+                server: server.to_string(),
+                expected: recorded.thumbprint.clone(),
+                presented: pin.thumbprint.clone(),
+            }),
+            None => {
+                servers.insert(server.to_string(), pin.clone());
+                self.write(&servers)
+            }
+        }
+    }
+
+    /// Removes the server, so the next certificate it presents is trusted.
+    pub fn remove(&self, server: &str) -> Result<(), LogInError> {
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut servers = self.read()?;
+        if servers.remove(server).is_some() {
+            self.write(&servers)?;
+        }
+        Ok(())
+    }
+
+    fn read(&self) -> Result<BTreeMap<String, CertificatePin>, LogInError> {
+        let data = match fs::read_to_string(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => return Err(self.error(e)),
+        };
+
+        let mut servers = BTreeMap::new();
+        for (number, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let algorithm = match fields.get(1) {
+                Some(&"SHA-256") => ThumbprintAlgorithm::Sha256,
+                Some(&"SHA-1") => ThumbprintAlgorithm::Sha1,
+                _ => return Err(self.line_error(number, "unknown algorithm")),
+            };
+            let pin = match fields.as_slice() {
+                [_, _, thumbprint] => CertificatePin::new(algorithm, thumbprint)
+                    .map_err(|e| self.line_error(number, &e.to_string()))?,
+                _ => {
+                    return Err(self.line_error(number, "expected server, algorithm and thumbprint"))
+                }
+            };
+            servers.insert(fields[0].to_string(), pin);
+        }
+
+        Ok(servers)
+    }
+
+    fn write(&self, servers: &BTreeMap<String, CertificatePin>) -> Result<(), LogInError> {
+        let mut data = String::from("# Server certificate thumbprints recorded by vauth\n");
+        for (server, pin) in servers {
+            data.push_str(&format!(
+                "{} {} {}\n",
+                server, pin.algorithm, pin.thumbprint
+            ));
+        }
+
+        replace_file(&self.path, data.as_bytes(), false).map_err(|e| self.error(e))
+    }
+
+    fn error(&self, e: std::io::Error) -> LogInError {
+        LogInError::KnownServersError(format!("{}: {}", self.path.display(), e))
+    }
+
+    fn line_error(&self, number: usize, message: &str) -> LogInError {
+        LogInError::KnownServersError(format!(
+            "{} line {}: {}",
+            self.path.display(),
+            number + 1,
+            message
+        ))
+    }
+}
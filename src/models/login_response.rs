@@ -1,11 +1,46 @@
 use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::Secret;
 
 /// Response structure for login requests to the Veeam REST API.
 /// Contains the access token, token type, refresh token, and expiration time.
+/// The time the token was issued is recorded by the library so that a saved token's
+/// expiry can be checked after it is loaded again.
+/// The tokens are redacted when it is printed with `Debug`.
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct LoginResponse {
-    pub access_token: String,
+    pub access_token: Secret,
     pub token_type: String,
-    pub refresh_token: String,
+    pub refresh_token: Secret,
     pub expires_in: i32,
+    /// When the token was issued, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub issued_at: Option<u64>,
+}
+
+impl LoginResponse {
+    /// Returns when the access token expires, or None if the issue time is not known.
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        let issued_at = UNIX_EPOCH + Duration::from_secs(self.issued_at?);
+        Some(issued_at + Duration::from_secs(self.expires_in.max(0) as u64))
+    }
+
+    /// Returns true if the access token has expired or will expire within the margin.
+    /// Tokens without an issue time are treated as expired.
+    pub fn is_expired(&self, margin: Duration) -> bool {
+        match self.expires_at() {
+            Some(expires_at) => SystemTime::now() + margin >= expires_at,
+            None => true,
+        }
+    }
+
+    /// Records the current time as the issue time.
+    pub(crate) fn issued_now(mut self) -> Self {
+        self.issued_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs());
+        self
+    }
 }
@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+
+use crate::LogInError;
+
+use super::{LoginResponse, Secret};
+
+/// The challenge returned by the VBR token endpoint instead of an access token when
+/// multi-factor authentication is enabled for the account.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MfaChallenge {
+    pub mfa_token: Secret,
+}
+
+/// Trait used by `VClientBuilder` to get the one-time code when the server returns an MFA challenge.
+/// It is implemented for closures, e.g. `|_challenge: &MfaChallenge| Ok(read_code())`.
+pub trait MfaCodeProvider: Send + Sync {
+    /// Returns the one-time code for the challenge.
+    fn code(&self, challenge: &MfaChallenge) -> Result<String, LogInError>;
+}
+
+impl<F> MfaCodeProvider for F
+where
+    F: Fn(&MfaChallenge) -> Result<String, LogInError> + Send + Sync,
+{
+    fn code(&self, challenge: &MfaChallenge) -> Result<String, LogInError> {
+        self(challenge)
+    }
+}
+
+/// Response from an OAuth token endpoint, either a token or an MFA challenge.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum TokenResponse {
+    Mfa(MfaChallenge),
+    Token(LoginResponse),
+}
+
+impl TokenResponse {
+    /// Returns the login response, an MFA challenge is treated as an error.
+    pub(crate) fn into_login_response(self) -> Result<LoginResponse, LogInError> {
+        match self {
+            TokenResponse::Token(login_response) => Ok(login_response),
+            TokenResponse::Mfa(_) => Err(LogInError::MfaRequired),
+        }
+    }
+}
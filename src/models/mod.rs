@@ -1,13 +1,48 @@
+pub mod address;
+pub mod api_error;
+pub(crate) mod api_version;
+pub mod certificate_pin;
+pub mod context_file;
+pub mod credentials;
 pub mod creds;
+pub mod known_servers;
 pub mod login_response;
+pub mod mfa;
+pub mod paginator;
+#[cfg(feature = "pinning")]
+pub(crate) mod pin_verifier;
+pub mod poller;
 pub mod profile;
+pub mod profile_file;
+pub mod retry_policy;
+pub mod secret;
+pub(crate) mod tls;
+pub mod token_store;
+pub mod vclient;
 pub mod vclient_builder;
 pub mod vprofile;
 pub mod vserver_builder;
 
-pub use creds::Creds;
+pub use address::{Address, Host};
+pub use api_error::ApiErrorBody;
+pub use certificate_pin::{CertificatePin, ThumbprintAlgorithm};
+pub use context_file::{Context, ContextFile, CredentialSource, TlsSettings};
+pub use credentials::{
+    CommandPassword, CredentialProvider, EnvPassword, FilePassword, PromptPassword, StaticPassword,
+};
+pub use creds::{Creds, MfaCreds, RefreshCreds};
+pub use known_servers::KnownServers;
 pub use login_response::LoginResponse;
-pub use profile::Profile;
+pub use mfa::{MfaChallenge, MfaCodeProvider};
+pub use paginator::{Page, Paginator};
+pub use poller::{PollCanceller, Poller, SessionResult, SessionStatus};
+pub use profile::{AuthStyle, Profile, ProfileType};
+pub use profile_file::{ProfileFile, ProfileFormat};
+pub use retry_policy::{RetryPolicy, RetryableError};
+pub use secret::Secret;
+pub use token_store::{FileTokenStore, TokenKey, TokenStore};
+pub use vclient::VClient;
 pub use vclient_builder::VClientBuilder;
 pub use vprofile::VProfile;
+#[allow(deprecated)]
 pub use vserver_builder::VServerBuilder;
@@ -0,0 +1,346 @@
+use futures_util::{
+    future,
+    stream::{self, BoxStream},
+    StreamExt, TryStreamExt,
+};
+use reqwest::Url;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::LogInError;
+
+use super::{api_error::api_error, profile::ProfileType, VClient};
+
+/// How a product's collection endpoints are paged.
+#[derive(Debug, Clone, Copy)]
+struct PageStyle {
+    /// The query parameter for the position of the page.
+    position: &'static str,
+    /// The query parameter for the page size.
+    limit: &'static str,
+    /// The position is a page number starting at 1 rather than the number of items to skip.
+    page_numbers: bool,
+    /// JSON pointers to the items, the total number of items, the number of pages and the next page link.
+    items: &'static str,
+    total: Option<&'static str>,
+    pages: Option<&'static str>,
+    next: Option<&'static str>,
+}
+
+impl PageStyle {
+    fn for_profile(profile_type: ProfileType) -> Self {
+        let style = PageStyle {
+            position: "Offset",
+            limit: "Limit",
+            page_numbers: false,
+            items: "/results",
+            total: Some("/totalCount"),
+            pages: None,
+            next: None,
+        };
+
+        match profile_type {
+            ProfileType::VBR => PageStyle {
+                position: "skip",
+                limit: "limit",
+                items: "/data",
+                total: Some("/pagination/total"),
+                ..style
+            },
+            ProfileType::VB365 => PageStyle {
+                position: "offset",
+                limit: "limit",
+                total: None,
+                next: Some("/_links/next/href"),
+                ..style
+            },
+            ProfileType::ENTMAN => PageStyle {
+                position: "page",
+                limit: "pageSize",
+                page_numbers: true,
+                items: "/Refs/Refs",
+                total: None,
+                pages: Some("/PagingInfo/PagesCount"),
+                ..style
+            },
+            ProfileType::VONE => PageStyle {
+                items: "/items",
+                ..style
+            },
+            ProfileType::VBAWS
+            | ProfileType::VBAZURE
+            | ProfileType::VBGCP
+            | ProfileType::UNKNOWN => style,
+        }
+    }
+}
+
+/// A page of items from a collection endpoint.
+#[derive(Debug, Clone)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// The total number of items in the collection, if the API reports it.
+    pub total: Option<u64>,
+}
+
+/// A page and the details needed to find the following pages.
+struct RawPage<T> {
+    page: Page<T>,
+    pages: Option<u64>,
+    next: Option<String>,
+}
+
+/// Pages through a collection endpoint, created with `VClient::paginate`.
+/// The query parameters and response format depend on the profile type:
+///
+/// | Profile                  | Parameters         | Items          | Count                   |
+/// | ------------------------ | ------------------ | -------------- | ----------------------- |
+/// | VBR                      | `skip`, `limit`    | `data`         | `pagination.total`      |
+/// | VB365                    | `offset`, `limit`  | `results`      | follows `_links.next`   |
+/// | ENTMAN                   | `page`, `pageSize` | `Refs.Refs`    | `PagingInfo.PagesCount` |
+/// | VONE                     | `Offset`, `Limit`  | `items`        | `totalCount`            |
+/// | VBAWS, VBAZURE and VBGCP | `Offset`, `Limit`  | `results`      | `totalCount`            |
+///
+/// When the number of items or pages is known from the first page, the remaining pages are
+/// requested concurrently. Otherwise they are requested one at a time until a page is short
+/// or there is no next link.
+///
+/// If the server returns fewer items than the page size on the first page, the remaining pages
+/// are requested with the size it returned. A later page other than the last with fewer items
+/// than the first returns a `PaginationError` rather than skipping items.
+#[derive(Clone)]
+pub struct Paginator {
+    client: VClient,
+    url: String,
+    style: PageStyle,
+    items: String,
+    page_size: u32,
+    concurrency: usize,
+}
+
+impl Paginator {
+    pub(crate) fn new(client: VClient, url: &str) -> Self {
+        let style = PageStyle::for_profile(client.profile().profile_type);
+        Paginator {
+            client,
+            url: url.to_string(),
+            style,
+            items: style.items.to_string(),
+            page_size: 100,
+            concurrency: 4,
+        }
+    }
+
+    /// Set the number of items requested per page, the default is 100.
+    /// The APIs limit the page size, so this should not be more than the product allows.
+    pub fn page_size(&mut self, page_size: u32) -> &mut Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    /// Set the maximum number of pages requested at the same time, the default is 4.
+    pub fn concurrency(&mut self, concurrency: usize) -> &mut Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Set the JSON pointer to the items in the response, e.g. `/Entities/BackupJobs/BackupJobs`
+    /// for an Enterprise Manager query with `format=Entities`.
+    pub fn items_pointer(&mut self, pointer: &str) -> &mut Self {
+        self.items = pointer.to_string();
+        self
+    }
+
+    /// Returns the total number of items, if the API reports it.
+    /// This requests a single page with one item.
+    pub async fn total(&self) -> Result<Option<u64>, LogInError> {
+        let url = self.page_url(0, 1)?;
+        Ok(self.fetch::<Value>(&url).await?.page.total)
+    }
+
+    /// Returns a stream of every item in the collection, in order.
+    pub fn items<T>(&self) -> BoxStream<'static, Result<T, LogInError>>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        self.pages::<T>()
+            .map_ok(|page| stream::iter(page.items.into_iter().map(Ok)))
+            .try_flatten()
+            .boxed()
+    }
+
+    /// Returns a stream of the pages in the collection, in order.
+    pub fn pages<T>(&self) -> BoxStream<'static, Result<Page<T>, LogInError>>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let mut paginator = self.clone();
+        stream::once(async move {
+            let first = match paginator.fetch_index::<T>(0).await {
+                Ok(first) => first,
+                Err(e) => return stream::once(future::ready(Err(e))).boxed(),
+            };
+
+            // A short first page of a larger collection means the server capped the page size,
+            // so the remaining offsets are worked out from the number of items it returned.
+            let received = first.page.items.len();
+            if !paginator.style.page_numbers
+                && received < paginator.page_size as usize
+                && first
+                    .page
+                    .total
+                    .is_some_and(|total| total > received as u64)
+            {
+                if received == 0 {
+                    let e = LogInError::PaginationError(format!(
+                        "the first page of {} is empty but the total is {}",
+                        paginator.url,
+                        first.page.total.unwrap_or_default()
+                    ));
+                    return stream::once(future::ready(Err(e))).boxed();
+                }
+                paginator.page_size = received as u32;
+            }
+
+            let page_count = first.pages.or_else(|| {
+                first
+                    .page
+                    .total
+                    .map(|total| total.div_ceil(paginator.page_size as u64))
+            });
+            let next = paginator.next_url(&first, 0);
+            let first_page = stream::once(future::ready(Ok(first.page)));
+
+            let Some(page_count) = page_count else {
+                return first_page
+                    .chain(paginator.sequential(next.map(|url| (1, url))))
+                    .boxed();
+            };
+
+            let concurrency = paginator.concurrency;
+            let rest = stream::iter(1..page_count)
+                .map(move |index| {
+                    let paginator = paginator.clone();
+                    async move {
+                        let page = paginator.fetch_index(index).await?.page;
+                        // Every page but the last should be as full as the first, otherwise
+                        // the following offsets would skip items.
+                        if index + 1 < page_count && page.items.len() < received {
+                            return Err(LogInError::PaginationError(format!(
+                                "page {} of {} has {} items rather than {}",
+                                index + 1,
+                                paginator.url,
+                                page.items.len(),
+                                received
+                            )));
+                        }
+                        Ok(page)
+                    }
+                })
+                .buffered(concurrency);
+            first_page.chain(rest).boxed()
+        })
+        .flatten()
+        .boxed()
+    }
+
+    /// Requests pages one at a time, following next links or moving on by the page size.
+    fn sequential<T>(
+        &self,
+        start: Option<(u64, String)>,
+    ) -> BoxStream<'static, Result<Page<T>, LogInError>>
+    where
+        T: DeserializeOwned + Send + 'static,
+    {
+        let paginator = self.clone();
+        stream::unfold(start, move |state| {
+            let paginator = paginator.clone();
+            async move {
+                let (index, url) = state?;
+                match paginator.fetch::<T>(&url).await {
+                    Ok(raw) => {
+                        let next = paginator.next_url(&raw, index).map(|url| (index + 1, url));
+                        Some((Ok(raw.page), next))
+                    }
+                    Err(e) => Some((Err(e), None)),
+                }
+            }
+        })
+        .boxed()
+    }
+
+    /// Returns the URL of the page after the one at the index, or None if it was the last page.
+    fn next_url<T>(&self, raw: &RawPage<T>, index: u64) -> Option<String> {
+        if self.style.next.is_some() {
+            let next = raw.next.as_ref()?;
+            return Url::parse(&self.url)
+                .ok()?
+                .join(next)
+                .ok()
+                .map(String::from);
+        }
+        if raw.page.items.len() < self.page_size as usize {
+            return None;
+        }
+        self.page_url(index + 1, self.page_size).ok()
+    }
+
+    /// Returns the collection URL with the position and page size query parameters set.
+    fn page_url(&self, index: u64, page_size: u32) -> Result<String, LogInError> {
+        let mut url = Url::parse(&self.url)
+            .map_err(|e| LogInError::PaginationError(format!("{}: {}", self.url, e)))?;
+
+        let position = if self.style.page_numbers {
+            index + 1
+        } else {
+            index * page_size as u64
+        };
+        let query: Vec<(String, String)> = url
+            .query_pairs()
+            .into_owned()
+            .filter(|(key, _)| key != self.style.position && key != self.style.limit)
+            .collect();
+
+        url.query_pairs_mut()
+            .clear()
+            .extend_pairs(query)
+            .append_pair(self.style.position, &position.to_string())
+            .append_pair(self.style.limit, &page_size.to_string());
+        Ok(url.into())
+    }
+
+    async fn fetch_index<T: DeserializeOwned>(&self, index: u64) -> Result<RawPage<T>, LogInError> {
+        let url = self.page_url(index, self.page_size)?;
+        self.fetch(&url).await
+    }
+
+    async fn fetch<T: DeserializeOwned>(&self, url: &str) -> Result<RawPage<T>, LogInError> {
+        let response = self.client.send(self.client.client().get(url)).await?;
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+
+        let mut body: Value = response.json().await?;
+        let items = body
+            .pointer_mut(&self.items)
+            .map(Value::take)
+            .ok_or_else(|| {
+                LogInError::PaginationError(format!("{} is missing from the response", self.items))
+            })?;
+        let number = |pointer: Option<&str>| body.pointer(pointer?).and_then(Value::as_u64);
+
+        Ok(RawPage {
+            page: Page {
+                items: serde_json::from_value(items)?,
+                total: number(self.style.total),
+            },
+            pages: number(self.style.pages),
+            next: self
+                .style
+                .next
+                .and_then(|pointer| body.pointer(pointer))
+                .and_then(Value::as_str)
+                .map(String::from),
+        })
+    }
+}
@@ -0,0 +1,211 @@
+use std::{
+    error::Error,
+    fmt, io,
+    sync::{Arc, Mutex},
+};
+
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    DigitallySignedStruct, OtherError, SignatureScheme,
+};
+
+use crate::LogInError;
+
+use super::{tls::ClientIdentity, CertificatePin, KnownServers, ThumbprintAlgorithm};
+
+/// The error for a certificate rejected by `PinVerifier`. It is carried in the rustls error of the
+/// failed handshake, so it is only returned for the request whose connection was rejected, in place
+/// of the reqwest connection error.
+#[derive(Debug)]
+struct RejectedCertificate {
+    message: String,
+    error: Mutex<Option<LogInError>>,
+}
+
+impl fmt::Display for RejectedCertificate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for RejectedCertificate {}
+
+/// Returns the reason the server's certificate was rejected if it is in the error's source chain,
+/// otherwise the error is returned unchanged.
+pub(crate) fn rejected_certificate(error: LogInError) -> LogInError {
+    let LogInError::ReqwestError(e) = &error else {
+        return error;
+    };
+
+    let mut sources: Vec<&(dyn Error + 'static)> = vec![e];
+    while let Some(source) = sources.pop() {
+        if let Some(rustls::Error::Other(other)) = source.downcast_ref::<rustls::Error>() {
+            if let Some(rejected) = other.0.downcast_ref::<RejectedCertificate>() {
+                if let Some(rejected) = rejected
+                    .error
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .take()
+                {
+                    return rejected;
+                }
+            }
+        }
+        // io::Error::source skips the error it wraps
+        if let Some(inner) = source
+            .downcast_ref::<io::Error>()
+            .and_then(io::Error::get_ref)
+        {
+            sources.push(inner);
+        }
+        sources.extend(source.source());
+    }
+
+    error
+}
+
+/// Accepts the server's certificate only if it matches one of the pins.
+/// The chain and hostname are not checked, as the pin identifies the exact certificate and
+/// Veeam servers normally use self-signed certificates.
+/// With known servers the recorded thumbprint is read on every handshake, so a client which
+/// reconnects later checks the certificate it recorded, and if there is none the certificate
+/// is trusted and its thumbprint recorded.
+#[derive(Debug)]
+pub(crate) struct PinVerifier {
+    pins: Vec<CertificatePin>,
+    known_servers: Option<(Arc<KnownServers>, String)>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl PinVerifier {
+    fn reject(&self, error: LogInError) -> rustls::Error {
+        rustls::Error::Other(OtherError(Arc::new(RejectedCertificate {
+            message: error.to_string(),
+            error: Mutex::new(Some(error)),
+        })))
+    }
+
+    /// Checks the certificate against the thumbprint recorded for the server, recording it if
+    /// there is none.
+    fn verify_known_server(
+        &self,
+        known_servers: &KnownServers,
+        server: &str,
+        end_entity: &CertificateDer<'_>,
+    ) -> Result<(), LogInError> {
+        match known_servers.get(server)? {
+            Some(recorded) if recorded.matches(end_entity) => Ok(()),
+            Some(recorded) => Err(LogInError::CertificateChanged {
+                server: server.to_string(),
+                presented: recorded.algorithm.thumbprint(end_entity),
+                expected: recorded.thumbprint,
+            }),
+            // First connection to the server, `add` fails if another connection recorded a
+            // different certificate in the meantime
+            None => {
+                let algorithm = ThumbprintAlgorithm::Sha256;
+                let pin = CertificatePin {
+                    algorithm,
+                    thumbprint: algorithm.thumbprint(end_entity),
+                };
+                known_servers.add(server, &pin)
+            }
+        }
+    }
+}
+
+impl ServerCertVerifier for PinVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if let Some((known_servers, server)) = &self.known_servers {
+            return match self.verify_known_server(known_servers, server, end_entity) {
+                Ok(()) => Ok(ServerCertVerified::assertion()),
+                Err(e) => Err(self.reject(e)),
+            };
+        }
+
+        if self.pins.iter().any(|pin| pin.matches(end_entity)) {
+            return Ok(ServerCertVerified::assertion());
+        }
+
+        let algorithm = self
+            .pins
+            .first()
+            .map(|pin| pin.algorithm)
+            .unwrap_or(ThumbprintAlgorithm::Sha256);
+        Err(self.reject(LogInError::CertificatePinMismatch {
+            algorithm,
+            presented: algorithm.thumbprint(end_entity),
+        }))
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Creates a rustls client config which only accepts certificates matching the pins, or with
+/// known servers the certificate recorded for the server.
+pub(crate) fn pinned_tls_config(
+    pins: Vec<CertificatePin>,
+    known_servers: Option<(Arc<KnownServers>, String)>,
+    identity: Option<ClientIdentity>,
+) -> Result<rustls::ClientConfig, LogInError> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let verifier = PinVerifier {
+        pins,
+        known_servers,
+        provider: provider.clone(),
+    };
+
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| LogInError::TlsConfigError(e.to_string()))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+
+    match identity {
+        Some(identity) => config
+            .with_client_auth_cert(identity.certs, identity.key)
+            .map_err(|e| LogInError::TlsConfigError(format!("client certificate: {}", e))),
+        None => Ok(config.with_no_client_auth()),
+    }
+}
@@ -0,0 +1,276 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use futures_util::future;
+use serde_json::Value;
+use tokio::{
+    sync::watch,
+    time::{self, Instant},
+};
+
+use crate::LogInError;
+
+use super::{api_error::api_error, profile::ProfileType, VClient};
+
+/// The final result of a session or task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionResult {
+    Success,
+    Warning,
+    /// The session failed or was stopped before it completed.
+    Failed,
+}
+
+impl SessionResult {
+    /// Maps the result and status strings used by the products, e.g. `Success`, `Succeeded`,
+    /// `Warning`, `Failed`, `Error` and `Canceled`. Returns None for anything else, such as `Running`.
+    pub fn from_status(status: &str) -> Option<Self> {
+        match status.to_ascii_lowercase().as_str() {
+            "success" | "succeeded" | "completed" => Some(SessionResult::Success),
+            "warning" => Some(SessionResult::Warning),
+            "failed" | "error" | "canceled" | "cancelled" | "stopped" => {
+                Some(SessionResult::Failed)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SessionResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionResult::Success => write!(f, "Success"),
+            SessionResult::Warning => write!(f, "Warning"),
+            SessionResult::Failed => write!(f, "Failed"),
+        }
+    }
+}
+
+/// The state of a session or task, read from the product's session or task resource.
+#[derive(Debug, Clone)]
+pub struct SessionStatus {
+    /// The state reported by the product, e.g. `Working` for VBR or `Running` for VB365.
+    pub state: Option<String>,
+    /// The result once the session has finished.
+    pub result: Option<SessionResult>,
+    /// The percentage complete, if the product reports it.
+    pub progress: Option<u32>,
+    /// The result message, if there is one.
+    pub message: Option<String>,
+    /// The full response body.
+    pub body: Value,
+}
+
+impl SessionStatus {
+    /// Reads the status from a session or task resource:
+    ///
+    /// | Profile | Finished when                      | Result                               | Progress          |
+    /// | ------- | ---------------------------------- | ------------------------------------ | ----------------- |
+    /// | VBR     | `state` is `Stopped`               | `result.result`, `result.isCanceled` | `progressPercent` |
+    /// | ENTMAN  | `State` is `Finished` or `Stopped` | `Result.Success` or `Result`         | `Progress`        |
+    /// | Others  | `status` is a result               | `status`                             | `progress`        |
+    pub fn parse(profile_type: ProfileType, body: Value) -> Self {
+        let text = |pointer: &str| {
+            body.pointer(pointer)
+                .and_then(Value::as_str)
+                .map(String::from)
+        };
+        let number = |pointer: &str| {
+            body.pointer(pointer)
+                .and_then(Value::as_u64)
+                .map(|n| n.min(100) as u32)
+        };
+
+        let (state, result, progress, message) = match profile_type {
+            ProfileType::VBR => {
+                let state = text("/state");
+                let result = match state.as_deref() {
+                    Some("Stopped")
+                        if body.pointer("/result/isCanceled") == Some(&Value::Bool(true)) =>
+                    {
+                        Some(SessionResult::Failed)
+                    }
+                    Some("Stopped") => text("/result/result")
+                        .as_deref()
+                        .and_then(SessionResult::from_status)
+                        .or(Some(SessionResult::Failed)),
+                    _ => None,
+                };
+                (
+                    state,
+                    result,
+                    number("/progressPercent"),
+                    text("/result/message"),
+                )
+            }
+            ProfileType::ENTMAN => {
+                let state = text("/State");
+                let result = match state.as_deref() {
+                    Some("Finished") | Some("Stopped") => match body.pointer("/Result") {
+                        Some(Value::Object(result)) => match result.get("Success") {
+                            Some(Value::Bool(true)) => Some(SessionResult::Success),
+                            _ => Some(SessionResult::Failed),
+                        },
+                        Some(Value::String(result)) => {
+                            SessionResult::from_status(result).or(Some(SessionResult::Failed))
+                        }
+                        _ => Some(SessionResult::Failed),
+                    },
+                    _ => None,
+                };
+                let message = text("/Result/Message").or_else(|| text("/Reason"));
+                (state, result, number("/Progress"), message)
+            }
+            _ => {
+                let state = text("/status").or_else(|| text("/state"));
+                let result = state.as_deref().and_then(SessionResult::from_status);
+                let message = text("/message").or_else(|| text("/statusMessage"));
+                (state, result, number("/progress"), message)
+            }
+        };
+
+        SessionStatus {
+            state,
+            result,
+            progress,
+            message,
+            body,
+        }
+    }
+
+    /// Returns true once the session has finished.
+    pub fn is_finished(&self) -> bool {
+        self.result.is_some()
+    }
+}
+
+/// Cancels a `Poller` that is waiting, from another task.
+/// Cancelling is permanent and applies to the poller it came from and all of its clones,
+/// so every `wait` on them returns `LogInError::PollCancelled`. Use `VClient::poll` to
+/// create a new poller for the same session.
+#[derive(Debug, Clone)]
+pub struct PollCanceller(Arc<watch::Sender<bool>>);
+
+impl PollCanceller {
+    /// Stops the poller, `Poller::wait` returns `LogInError::PollCancelled`.
+    /// This only stops polling, the session on the server is not stopped.
+    pub fn cancel(&self) {
+        self.0.send_replace(true);
+    }
+}
+
+type ProgressFn = dyn Fn(&SessionStatus) + Send + Sync;
+
+/// Polls a session or task resource until it finishes, created with `VClient::poll`.
+/// The URL is the resource returned when the operation was started, e.g. `sessions/{id}` for VBR,
+/// `RestoreSessions/{id}` for VB365 or `tasks/{id}` for Enterprise Manager.
+/// See `SessionStatus::parse` for how each product's status is read.
+#[derive(Clone)]
+pub struct Poller {
+    client: VClient,
+    url: String,
+    interval: Duration,
+    timeout: Option<Duration>,
+    on_progress: Option<Arc<ProgressFn>>,
+    cancel: Arc<watch::Sender<bool>>,
+}
+
+impl Poller {
+    pub(crate) fn new(client: VClient, url: &str) -> Self {
+        Poller {
+            client,
+            url: url.to_string(),
+            interval: Duration::from_secs(5),
+            timeout: None,
+            on_progress: None,
+            cancel: Arc::new(watch::channel(false).0),
+        }
+    }
+
+    /// Set the time between polls, the default is 5 seconds.
+    pub fn interval(&mut self, interval: Duration) -> &mut Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set how long to wait for the session to finish before returning `LogInError::PollTimeout`.
+    /// By default there is no timeout.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set a callback which is called with the status after every poll.
+    pub fn on_progress(
+        &mut self,
+        callback: impl Fn(&SessionStatus) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.on_progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Returns a handle which can cancel `wait` from another task.
+    /// Clones of the poller share the handle, see `PollCanceller`.
+    /// Dropping the `wait` future also stops polling.
+    pub fn canceller(&self) -> PollCanceller {
+        PollCanceller(self.cancel.clone())
+    }
+
+    /// Requests the session or task once and returns its status.
+    pub async fn status(&self) -> Result<SessionStatus, LogInError> {
+        let response = self
+            .client
+            .send(self.client.client().get(&self.url))
+            .await?;
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+
+        let body: Value = response.json().await?;
+        Ok(SessionStatus::parse(
+            self.client.profile().profile_type,
+            body,
+        ))
+    }
+
+    /// Polls until the session finishes and returns its final status. A session which failed is
+    /// returned as `Ok` with `SessionResult::Failed`, errors are for requests which fail,
+    /// a timeout or cancellation. The timeout and cancellation also stop a request in progress.
+    pub async fn wait(&self) -> Result<SessionStatus, LogInError> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut cancelled = self.cancel.subscribe();
+
+        loop {
+            let status = tokio::select! {
+                biased;
+                _ = cancelled.wait_for(|cancelled| *cancelled) => return Err(LogInError::PollCancelled),
+                _ = Self::deadline(deadline) => return Err(self.timed_out()),
+                status = self.status() => status?,
+            };
+            if let Some(on_progress) = &self.on_progress {
+                on_progress(&status);
+            }
+            if status.is_finished() {
+                return Ok(status);
+            }
+
+            tokio::select! {
+                biased;
+                _ = cancelled.wait_for(|cancelled| *cancelled) => return Err(LogInError::PollCancelled),
+                _ = Self::deadline(deadline) => return Err(self.timed_out()),
+                _ = time::sleep(self.interval) => {}
+            }
+        }
+    }
+
+    /// Waits until the deadline, or forever if there is no timeout.
+    async fn deadline(deadline: Option<Instant>) {
+        match deadline {
+            Some(deadline) => time::sleep_until(deadline).await,
+            None => future::pending().await,
+        }
+    }
+
+    fn timed_out(&self) -> LogInError {
+        LogInError::PollTimeout(self.timeout.unwrap_or_default())
+    }
+}
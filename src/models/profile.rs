@@ -1,10 +1,12 @@
+use super::address::Address;
+use super::api_version::VersionDiscovery;
 use super::vprofile::VProfile;
-use crate::{check_valid_ip, LogInError, LoginResponse};
+use crate::{LogInError, LoginResponse, Secret};
 use reqwest::header::HeaderValue;
 use serde::{Deserialize, Serialize};
 
 /// Enum representing different profile types for Veeam REST API.
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 pub enum ProfileType {
     VBAZURE,
     VBR,
@@ -16,21 +18,143 @@ pub enum ProfileType {
     UNKNOWN,
 }
 
+/// How a product authenticates and sends the session token.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AuthStyle {
+    /// An OAuth password grant to the token endpoint, the access token is sent as a bearer token.
+    #[default]
+    OAuth,
+    /// Basic auth to the session endpoint, the session id is returned and sent in the
+    /// `X-RestSvcSessionId` header. This is used by Enterprise Manager.
+    Session,
+}
+
+impl AuthStyle {
+    /// Returns the auth style the product uses.
+    pub fn for_profile_type(profile_type: ProfileType) -> Self {
+        match profile_type {
+            ProfileType::ENTMAN => AuthStyle::Session,
+            _ => AuthStyle::OAuth,
+        }
+    }
+}
+
 /// Profile used to authenticate to the Veeam REST API.
-/// It contains the name of the profile, URL, port, API version, and X-API-Version.
-#[derive(Debug, Serialize, Deserialize)]
+/// It contains the name of the profile, the parts used to build the token and API URLs,
+/// the API version, and X-API-Version.
+///
+/// URLs are built as `{scheme}://{address}:{port}{base_path}/{api_version}/{end_point}` and
+/// `{scheme}://{address}:{port}{token_path}`. The token path can contain `{api_version}`
+/// which is replaced with the API version.
+///
+/// Profiles saved with the old `url` field, e.g. `":9419/api/oauth2/token"`, can still be
+/// deserialized and are converted to the new fields.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "ProfileData")]
 pub struct Profile {
     pub profile_type: ProfileType,
     pub name: String,
-    pub url: String,
+    /// The URL scheme, normally `https`.
+    pub scheme: String,
+    /// The port, this can be empty where the default port for the scheme is used.
     pub port: String,
+    /// The path the API version and end point are appended to, e.g. `/api`.
+    pub base_path: String,
+    /// The path of the token endpoint, e.g. `/api/oauth2/token`.
+    pub token_path: String,
+    /// The path of the sign-out endpoint, if the product has one. `{session_id}` is replaced
+    /// with the access token, e.g. `/api/logonSessions/{session_id}`.
+    pub logout_path: Option<String>,
     pub api_version: String,
     pub x_api_version: Option<String>,
+    /// How to log in and send the token, if it is missing when deserializing it is set from the profile type.
+    pub auth_style: AuthStyle,
+}
+
+/// Serialized form of the Profile, which also accepts the old `url` field.
+#[derive(Deserialize)]
+struct ProfileData {
+    #[serde(default = "unknown_profile_type")]
+    profile_type: ProfileType,
+    name: String,
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default = "default_scheme")]
+    scheme: String,
+    #[serde(default)]
+    port: String,
+    #[serde(default)]
+    base_path: String,
+    #[serde(default)]
+    token_path: String,
+    #[serde(default)]
+    logout_path: Option<String>,
+    #[serde(default)]
+    api_version: String,
+    #[serde(default)]
+    x_api_version: Option<String>,
+    #[serde(default)]
+    auth_style: Option<AuthStyle>,
+}
+
+fn unknown_profile_type() -> ProfileType {
+    ProfileType::UNKNOWN
+}
+
+fn default_scheme() -> String {
+    String::from("https")
+}
+
+impl From<ProfileData> for Profile {
+    fn from(data: ProfileData) -> Self {
+        let mut port = data.port;
+        let mut token_path = data.token_path;
+
+        if let Some(url) = data.url.filter(|_| token_path.is_empty()) {
+            let (url_port, path) = split_legacy_url(&url);
+            if port.is_empty() {
+                port = url_port.to_string();
+            }
+            token_path = path.to_string();
+        }
+
+        Profile {
+            profile_type: data.profile_type,
+            name: data.name,
+            scheme: data.scheme,
+            port,
+            base_path: data.base_path,
+            token_path,
+            logout_path: data.logout_path,
+            api_version: data.api_version,
+            x_api_version: data.x_api_version,
+            auth_style: data
+                .auth_style
+                .unwrap_or_else(|| AuthStyle::for_profile_type(data.profile_type)),
+        }
+    }
+}
+
+/// Splits the old url form, `:9419/api/oauth2/token`, into the port and path.
+fn split_legacy_url(url: &str) -> (&str, &str) {
+    match url.strip_prefix(':') {
+        Some(rest) => {
+            let end = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            rest.split_at(end)
+        }
+        None => ("", url),
+    }
 }
 
 impl Profile {
     /// Creates a new Profile instance.
-    /// This method initializes a Profile with the given parameters.
+    /// This method initializes a Profile with the given parameters, the url is in the old
+    /// form with the port at the start, e.g. `:1234/api/oauth2/token`. The port in the url
+    /// is only used if the port parameter is empty.
+    /// As the profile type is unknown `build_url` cannot be used, set `profile_type` and
+    /// `base_path` if it is required.
     pub fn new(
         name: String,
         url: String,
@@ -38,45 +162,110 @@ impl Profile {
         api_version: String,
         x_api_version: Option<String>,
     ) -> Self {
-        Profile {
+        Profile::from(ProfileData {
             profile_type: ProfileType::UNKNOWN,
             name,
-            url,
+            url: Some(url),
+            scheme: default_scheme(),
             port,
+            base_path: String::new(),
+            token_path: String::new(),
+            logout_path: None,
             api_version,
             x_api_version,
+            auth_style: None,
+        })
+    }
+
+    /// Returns the token endpoint in the old url form, e.g. `:9419/api/oauth2/token`.
+    #[deprecated(since = "3.0.0", note = "Use the port and token_path fields instead")]
+    pub fn url(&self) -> String {
+        match self.port.as_str() {
+            "" => self.token_path(),
+            port => format!(":{}{}", port, self.token_path()),
         }
     }
 
+    /// Returns the token path with the API version filled in.
+    fn token_path(&self) -> String {
+        self.token_path.replace("{api_version}", &self.api_version)
+    }
+
+    /// Builds the URL of the sign-out endpoint for a session, or None if the product does not have one.
+    pub fn logout_url(
+        &self,
+        address: &str,
+        session_id: &str,
+    ) -> Result<Option<String>, LogInError> {
+        let Some(logout_path) = &self.logout_path else {
+            return Ok(None);
+        };
+        let address = Address::parse(address)?;
+        let path = logout_path
+            .replace("{api_version}", &self.api_version)
+            .replace("{session_id}", session_id);
+
+        Ok(Some(format!(
+            "{}://{}{}",
+            self.scheme,
+            address.authority(&self.port),
+            path
+        )))
+    }
+
     /// Builds the URL for the Veeam REST API based on the profile.
     /// It takes the address and end point as parameters and returns a formatted URL.
-    pub fn build_url(&self, address: &String, end_point: &String) -> Result<String, LogInError> {
-        if !check_valid_ip(address) {
-            return Err(LogInError::IpAddressError);
-        }
+    /// The address can be an IP address or hostname, if it includes a port this is used
+    /// instead of the profile port.
+    pub fn build_url(&self, address: &str, end_point: &str) -> Result<String, LogInError> {
+        let address = Address::parse(address)?;
 
-        match self.profile_type {
-            ProfileType::VBAZURE => Ok(format!(
-                "https://{}/api/{}/{}",
-                address, self.api_version, end_point
-            )),
-            ProfileType::VBR | ProfileType::VBAWS | ProfileType::VBGCP | ProfileType::VONE => Ok(format!(
-                "https://{}:{}/api/{}/{}",
-                address, self.port, self.api_version, end_point
-            )),
-            ProfileType::VB365 => Ok(format!(
-                "https://{}:{}/{}/{}",
-                address, self.port, self.api_version, end_point
-            )),
-            ProfileType::ENTMAN => Ok(format!(
-                "https://{}:{}/api/{}",
-                address, self.port, end_point
-            )),
-            ProfileType::UNKNOWN => Err(LogInError::OtherError(
+        if self.profile_type == ProfileType::UNKNOWN {
+            return Err(LogInError::OtherError(
                 "Unknown profile type, manual endpoint construction required".to_string(),
-            ))
+            ));
         }
+
+        let mut url = format!(
+            "{}://{}{}",
+            self.scheme,
+            address.authority(&self.port),
+            self.base_path.trim_end_matches('/')
+        );
+        if !self.api_version.is_empty() {
+            url.push('/');
+            url.push_str(&self.api_version);
+        }
+        url.push('/');
+        url.push_str(end_point.trim_start_matches('/'));
+
+        Ok(url)
+    }
+
+    /// Builds the URL of the token endpoint used to authenticate.
+    pub fn token_url(&self, address: &str) -> Result<String, LogInError> {
+        let address = Address::parse(address)?;
+
+        Ok(format!(
+            "{}://{}{}",
+            self.scheme,
+            address.authority(&self.port),
+            self.token_path()
+        ))
+    }
+
+    /// Sets the API version and X-API-Version to the newest this library supports for the
+    /// product version, e.g. `12.1.2.172` for VBR. This is done by `VClientBuilder::discover_api_version`.
+    /// Returns `LogInError::UnsupportedServerVersion` if the product version is too old, profiles
+    /// without known API versions are left unchanged.
+    pub fn negotiate_api_version(&mut self, server_version: &str) -> Result<(), LogInError> {
+        if let Some(discovery) = VersionDiscovery::for_profile(self.profile_type) {
+            if let Some(newest) = discovery.supported(self, server_version)?.last() {
+                newest.apply(self);
+            }
         }
+        Ok(())
+    }
 
     #[deprecated(since = "0.1.0", note = "Use VProfile::<enum>.profile_data() instead")]
     /// Returns the profile data for the given VProfile.
@@ -91,7 +280,7 @@ impl Profile {
     /// Note that this is a breaking change from the previous version.
     pub fn build_auth_headers(
         &self,
-        token: &String,
+        token: &str,
     ) -> Result<reqwest::header::HeaderMap, reqwest::header::InvalidHeaderValue> {
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert(
@@ -103,11 +292,16 @@ impl Profile {
             HeaderValue::from_static("application/json"),
         );
 
-        if self.profile_type == ProfileType::ENTMAN {
-            headers.insert("X-RestSvcSessionId", HeaderValue::from_str(token)?);
+        // The token headers are marked sensitive so they are not shown by `Debug`
+        if self.auth_style == AuthStyle::Session {
+            let mut session_id = HeaderValue::from_str(token)?;
+            session_id.set_sensitive(true);
+            headers.insert("X-RestSvcSessionId", session_id);
         } else {
-            let bearer = format!("Bearer {}", token);
-            headers.insert("Authorization", HeaderValue::from_str(&bearer)?);
+            let bearer = Secret::new(format!("Bearer {}", token));
+            let mut authorization = HeaderValue::from_str(bearer.expose_secret())?;
+            authorization.set_sensitive(true);
+            headers.insert("Authorization", authorization);
             if let Some(x_api_version) = &self.x_api_version {
                 headers.insert("X-Api-Version", HeaderValue::from_str(x_api_version)?);
             }
@@ -123,29 +317,6 @@ impl Profile {
         &self,
         login_response: &LoginResponse,
     ) -> Result<reqwest::header::HeaderMap, reqwest::header::InvalidHeaderValue> {
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert(
-            reqwest::header::ACCEPT,
-            HeaderValue::from_static("application/json"),
-        );
-        headers.insert(
-            reqwest::header::CONTENT_TYPE,
-            HeaderValue::from_static("application/json"),
-        );
-
-        if self.name == "ENTMAN" {
-            headers.insert(
-                "X-RestSvcSessionId",
-                HeaderValue::from_str(&login_response.access_token)?,
-            );
-        } else {
-            let bearer = format!("Bearer {}", login_response.access_token);
-            headers.insert("Authorization", bearer.parse()?);
-            if let Some(x_api_version) = &self.x_api_version {
-                headers.insert("X-Api-Version", HeaderValue::from_str(x_api_version)?);
-            }
-        }
-
-        Ok(headers)
+        self.build_auth_headers(login_response.access_token.expose_secret())
     }
 }
@@ -0,0 +1,341 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use reqwest::header::HeaderValue;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+
+use crate::LogInError;
+
+use super::{
+    profile::{AuthStyle, ProfileType},
+    Profile, VProfile,
+};
+
+/// The format of a profile or context file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileFormat {
+    Json,
+    /// Requires the `toml` feature.
+    Toml,
+    /// Requires the `yaml` feature.
+    Yaml,
+}
+
+impl ProfileFormat {
+    /// Returns the format for the file extension, `json`, `toml`, `yaml` or `yml`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "json" => Some(ProfileFormat::Json),
+            "toml" => Some(ProfileFormat::Toml),
+            "yaml" | "yml" => Some(ProfileFormat::Yaml),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ProfileFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileFormat::Json => write!(f, "JSON"),
+            ProfileFormat::Toml => write!(f, "TOML"),
+            ProfileFormat::Yaml => write!(f, "YAML"),
+        }
+    }
+}
+
+/// The top level of a profile file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileFileData {
+    #[serde(default)]
+    profiles: BTreeMap<String, ProfileEntry>,
+}
+
+/// A profile in a file, fields which are missing are taken from the built-in profile it extends.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ProfileEntry {
+    extends: Option<String>,
+    profile_type: Option<ProfileType>,
+    scheme: Option<String>,
+    #[serde(default, deserialize_with = "port_string")]
+    port: Option<String>,
+    base_path: Option<String>,
+    token_path: Option<String>,
+    logout_path: Option<String>,
+    api_version: Option<String>,
+    x_api_version: Option<String>,
+    auth_style: Option<AuthStyle>,
+}
+
+/// Accepts the port as a number or a string.
+fn port_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Port {
+        Number(u64),
+        Text(String),
+    }
+
+    Ok(
+        Option::<Port>::deserialize(deserializer)?.map(|port| match port {
+            Port::Number(port) => port.to_string(),
+            Port::Text(port) => port,
+        }),
+    )
+}
+
+/// Named profiles loaded from a JSON, TOML or YAML file, so ports, paths and versions can be
+/// changed without a code change. Each profile is under `profiles`, keyed by its name:
+///
+/// ```toml
+/// # Overrides the built-in VBR profile
+/// [profiles.VBR]
+/// port = 9420
+/// x_api_version = "1.1-rev2"
+///
+/// # A new profile based on a built-in one
+/// [profiles.vbr-lab]
+/// extends = "VBR"
+/// port = 443
+/// base_path = "/vbr/api"
+/// token_path = "/vbr/api/oauth2/token"
+///
+/// # A profile with every field
+/// [profiles.em-proxy]
+/// profile_type = "ENTMAN"
+/// scheme = "https"
+/// port = 8443
+/// base_path = "/api"
+/// token_path = "/api/sessionMngr/?v=latest"
+/// logout_path = "/api/logonSessions/{session_id}"
+/// auth_style = "Session"
+/// ```
+///
+/// A profile named after a built-in `VProfile`, or with `extends`, starts from the built-in
+/// defaults. Built-in names are not case sensitive, so `[profiles.vbr]` overrides `VBR` and
+/// a file cannot override the same built-in profile twice. Other profiles need `profile_type` and `token_path`, and the auth style defaults
+/// to the one the profile type uses. Every profile is checked when the file is loaded.
+#[derive(Debug, Clone, Default)]
+pub struct ProfileFile {
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl ProfileFile {
+    /// Load the profiles from a file, the format is chosen from the extension.
+    pub fn load(path: impl Into<PathBuf>) -> Result<Self, LogInError> {
+        let path = path.into();
+        let error = |message: String| {
+            LogInError::ProfileFileError(format!("{}: {}", path.display(), message))
+        };
+
+        let format = ProfileFormat::from_path(&path)
+            .ok_or_else(|| error("the extension must be json, toml, yaml or yml".to_string()))?;
+        let data = fs::read_to_string(&path).map_err(|e| error(e.to_string()))?;
+
+        ProfileFile::parse(&data, format).map_err(|e| match e {
+            LogInError::ProfileFileError(message) => error(message),
+            e => e,
+        })
+    }
+
+    /// Parse the profiles from a string in the format.
+    pub fn parse(data: &str, format: ProfileFormat) -> Result<Self, LogInError> {
+        let data: ProfileFileData =
+            deserialize(data, format).map_err(LogInError::ProfileFileError)?;
+        ProfileFile::from_entries(data.profiles)
+    }
+
+    /// Resolves and checks the profiles, this is also used for the profiles in a context file.
+    pub(crate) fn from_entries(
+        entries: BTreeMap<String, ProfileEntry>,
+    ) -> Result<Self, LogInError> {
+        let mut profiles: BTreeMap<String, Profile> = BTreeMap::new();
+        for (name, entry) in entries {
+            let key = profile_key(&name);
+            if profiles.contains_key(&key) {
+                return Err(LogInError::ProfileFileError(format!(
+                    "profile `{}`: the built-in {} profile is overridden more than once",
+                    name, key
+                )));
+            }
+            let profile = resolve(&key, entry)
+                .and_then(|profile| validate(&profile).map(|_| profile))
+                .map_err(|e| LogInError::ProfileFileError(format!("profile `{}`: {}", name, e)))?;
+            profiles.insert(key, profile);
+        }
+
+        Ok(ProfileFile { profiles })
+    }
+
+    /// Returns the profile defined in the file.
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.get(&profile_key(name))
+    }
+
+    /// Returns the profile defined in the file, or the built-in profile with the name if the
+    /// file does not define it.
+    pub fn profile(&self, name: &str) -> Result<Profile, LogInError> {
+        self.get(name)
+            .cloned()
+            .or_else(|| VProfile::from_name(name).map(|v_profile| v_profile.profile_data()))
+            .ok_or_else(|| {
+                LogInError::ProfileFileError(format!("profile `{}` is not defined", name))
+            })
+    }
+
+    /// Returns the names of the profiles defined in the file.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+}
+
+/// Returns the name a profile is stored under. Built-in profile names are not case sensitive,
+/// so an override of one is stored under the built-in name, e.g. `vbr` is stored as `VBR`.
+fn profile_key(name: &str) -> String {
+    match VProfile::from_name(name) {
+        Some(v_profile) => v_profile.profile_data().name,
+        None => name.to_string(),
+    }
+}
+
+/// Deserializes a file in the format, returning the parser's error message.
+pub(crate) fn deserialize<T: DeserializeOwned>(
+    data: &str,
+    format: ProfileFormat,
+) -> Result<T, String> {
+    match format {
+        ProfileFormat::Json => serde_json::from_str(data).map_err(|e| e.to_string()),
+        #[cfg(feature = "toml")]
+        ProfileFormat::Toml => toml::from_str(data).map_err(|e| e.to_string()),
+        #[cfg(feature = "yaml")]
+        ProfileFormat::Yaml => serde_norway::from_str(data).map_err(|e| e.to_string()),
+        #[allow(unreachable_patterns)]
+        format => Err(format!(
+            "{} files require the `{}` feature",
+            format,
+            format.to_string().to_ascii_lowercase()
+        )),
+    }
+}
+
+/// Builds the profile from the entry and the built-in profile it is based on.
+fn resolve(name: &str, entry: ProfileEntry) -> Result<Profile, String> {
+    let base = match &entry.extends {
+        Some(extends) => Some(VProfile::from_name(extends).ok_or_else(|| {
+            format!(
+                "extends `{}` which is not a built-in profile, use one of VBR, VB365, VBAWS, VBAZURE, VBGCP, VONE or ENTMAN",
+                extends
+            )
+        })?),
+        None => VProfile::from_name(name),
+    };
+
+    let mut profile = match base {
+        Some(v_profile) => v_profile.profile_data(),
+        None => {
+            let profile_type = entry.profile_type.ok_or(
+                "profile_type is required as the profile does not extend a built-in profile",
+            )?;
+            Profile {
+                profile_type,
+                name: String::new(),
+                scheme: String::from("https"),
+                port: String::new(),
+                base_path: String::new(),
+                token_path: entry.token_path.clone().ok_or(
+                    "token_path is required as the profile does not extend a built-in profile",
+                )?,
+                logout_path: None,
+                api_version: String::new(),
+                x_api_version: None,
+                auth_style: AuthStyle::for_profile_type(profile_type),
+            }
+        }
+    };
+
+    profile.name = name.to_string();
+    if let Some(profile_type) = entry.profile_type {
+        profile.profile_type = profile_type;
+        profile.auth_style = AuthStyle::for_profile_type(profile_type);
+    }
+    if let Some(auth_style) = entry.auth_style {
+        profile.auth_style = auth_style;
+    }
+    if let Some(scheme) = entry.scheme {
+        profile.scheme = scheme;
+    }
+    if let Some(port) = entry.port {
+        profile.port = port;
+    }
+    if let Some(base_path) = entry.base_path {
+        profile.base_path = base_path;
+    }
+    if let Some(token_path) = entry.token_path {
+        profile.token_path = token_path;
+    }
+    if let Some(logout_path) = entry.logout_path {
+        profile.logout_path = Some(logout_path).filter(|path| !path.is_empty());
+    }
+    if let Some(api_version) = entry.api_version {
+        profile.api_version = api_version;
+    }
+    if let Some(x_api_version) = entry.x_api_version {
+        profile.x_api_version = Some(x_api_version).filter(|version| !version.is_empty());
+    }
+
+    Ok(profile)
+}
+
+/// Checks the profile can be used to build URLs and headers.
+fn validate(profile: &Profile) -> Result<(), String> {
+    if profile.profile_type == ProfileType::UNKNOWN {
+        return Err("profile_type cannot be UNKNOWN as URLs cannot be built for it".to_string());
+    }
+    if profile.scheme != "https" && profile.scheme != "http" {
+        return Err(format!(
+            "scheme must be https or http, not `{}`",
+            profile.scheme
+        ));
+    }
+    if !profile.port.is_empty() && !matches!(profile.port.parse::<u16>(), Ok(port) if port > 0) {
+        return Err(format!(
+            "port must be a number from 1 to 65535, not `{}`",
+            profile.port
+        ));
+    }
+
+    let paths = [
+        (
+            "base_path",
+            Some(&profile.base_path).filter(|p| !p.is_empty()),
+        ),
+        ("token_path", Some(&profile.token_path)),
+        ("logout_path", profile.logout_path.as_ref()),
+    ];
+    for (field, path) in paths {
+        if let Some(path) = path.filter(|path| !path.starts_with('/')) {
+            return Err(format!("{} must start with /, not `{}`", field, path));
+        }
+    }
+
+    if profile.api_version.contains(['/', ' ']) {
+        return Err(format!(
+            "api_version must not contain / or spaces, not `{}`",
+            profile.api_version
+        ));
+    }
+    if let Some(x_api_version) = &profile.x_api_version {
+        HeaderValue::from_str(x_api_version).map_err(|_| {
+            format!(
+                "x_api_version `{}` is not a valid header value",
+                x_api_version
+            )
+        })?;
+    }
+
+    Ok(())
+}
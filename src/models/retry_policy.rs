@@ -0,0 +1,179 @@
+use std::{collections::hash_map::RandomState, hash::BuildHasher, time::Duration};
+
+use reqwest::{header::RETRY_AFTER, RequestBuilder, Response, StatusCode};
+
+use crate::{utils::trace::event, LogInError};
+
+use super::api_error::api_error;
+
+/// The kinds of request error which can be retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryableError {
+    /// The connection could not be made, e.g. it was refused while the service restarts.
+    Connect,
+    /// The request or connection timed out.
+    Timeout,
+    /// The connection failed while sending the request or reading the response, e.g. it was reset.
+    Request,
+}
+
+/// Policy for retrying requests that fail with a transient error, set with `VClientBuilder::retry`.
+/// It applies to login and refresh requests, and to requests sent with a `VClient` if `api_calls` is set.
+///
+/// The delay before each retry starts at `initial_backoff` and is multiplied by `multiplier` each time,
+/// up to `max_backoff`. With `jitter` the delay is a random duration between half and all of this,
+/// so many clients do not retry at the same time. A `Retry-After` header is used if it is longer.
+///
+/// ```no run
+/// let policy = RetryPolicy {
+///     max_attempts: 5,
+///     initial_backoff: Duration::from_secs(1),
+///     ..RetryPolicy::default()
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// The total number of attempts including the first, the default is 3.
+    pub max_attempts: u32,
+    /// The delay before the first retry, the default is 500 milliseconds.
+    pub initial_backoff: Duration,
+    /// The longest delay between attempts, the default is 10 seconds.
+    pub max_backoff: Duration,
+    /// The factor the delay is multiplied by after each attempt, the default is 2.
+    pub multiplier: f64,
+    /// Randomise the delay, the default is true.
+    pub jitter: bool,
+    /// Response statuses which are retried, the default is 429, 502, 503 and 504.
+    pub retry_statuses: Vec<StatusCode>,
+    /// Request errors which are retried, the default is all of them.
+    pub retry_errors: Vec<RetryableError>,
+    /// Also retry requests sent with a `VClient`, the default is false.
+    /// Only enable this if the requests are safe to repeat.
+    pub api_calls: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            multiplier: 2.0,
+            jitter: true,
+            retry_statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            retry_errors: vec![
+                RetryableError::Connect,
+                RetryableError::Timeout,
+                RetryableError::Request,
+            ],
+            api_calls: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the delay after the attempt before the next one, without jitter.
+    /// The first attempt is 1.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = self
+            .multiplier
+            .max(1.0)
+            .powi(attempt.saturating_sub(1).min(i32::MAX as u32) as i32);
+        self.initial_backoff
+            .mul_f64(factor.min(u32::MAX as f64))
+            .min(self.max_backoff)
+    }
+
+    /// Returns true if the request error can be retried.
+    pub fn is_retryable(&self, error: &reqwest::Error) -> bool {
+        self.retry_errors.iter().any(|kind| match kind {
+            RetryableError::Connect => error.is_connect(),
+            RetryableError::Timeout => error.is_timeout(),
+            RetryableError::Request => error.is_request() || error.is_body(),
+        })
+    }
+
+    fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let mut delay = self.backoff(attempt);
+        if self.jitter {
+            // RandomState is seeded randomly, which is enough to spread out retries
+            let random = RandomState::new().hash_one(attempt) as f64 / u64::MAX as f64;
+            delay = delay.mul_f64(0.5 + random / 2.0);
+        }
+        match retry_after {
+            Some(retry_after) => delay.max(retry_after.min(self.max_backoff)),
+            None => delay,
+        }
+    }
+
+    /// Sends the request until it succeeds, fails with an error that cannot be retried,
+    /// or runs out of attempts. Request errors are converted with `map_error`, and are not
+    /// retried if it returns anything other than `LogInError::ReqwestError`.
+    pub(crate) async fn send(
+        &self,
+        request: RequestBuilder,
+        map_error: impl Fn(reqwest::Error) -> LogInError,
+    ) -> Result<Response, LogInError> {
+        let mut errors = Vec::new();
+        let max_attempts = self.max_attempts.max(1);
+
+        for attempt in 1..=max_attempts {
+            // Requests with streaming bodies cannot be sent again
+            let Some(attempt_request) = request.try_clone() else {
+                return request.send().await.map_err(map_error);
+            };
+
+            let (error, retry_after) = match attempt_request.send().await {
+                Ok(response) if !self.retry_statuses.contains(&response.status()) => {
+                    return Ok(response)
+                }
+                // Without earlier failures the caller handles the status as if it was not retried,
+                // otherwise the last status is returned along with their errors
+                Ok(response) if attempt == max_attempts && errors.is_empty() => {
+                    return Ok(response)
+                }
+                Ok(response) => {
+                    let retry_after = response
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.trim().parse().ok())
+                        .map(Duration::from_secs);
+                    (api_error(response).await, retry_after)
+                }
+                Err(e) => {
+                    let retryable = self.is_retryable(&e);
+                    let error = map_error(e);
+                    if !retryable || !matches!(error, LogInError::ReqwestError(_)) {
+                        errors.push(error);
+                        break;
+                    }
+                    (error, None)
+                }
+            };
+
+            if attempt < max_attempts {
+                let delay = self.delay(attempt, retry_after);
+                event!(
+                    WARN,
+                    attempt = attempt,
+                    delay_ms = delay.as_millis(),
+                    error = error,
+                    "request failed, retrying"
+                );
+                tokio::time::sleep(delay).await;
+            }
+            errors.push(error);
+        }
+
+        Err(match errors.len() {
+            1 => errors.remove(0),
+            _ => LogInError::RetryError(errors),
+        })
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "pinning")]
+use base64::{engine::general_purpose::STANDARD, Engine};
+#[cfg(feature = "pinning")]
+use p12_keystore::{KeyStore, Pkcs12ImportPolicy};
+#[cfg(feature = "pinning")]
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+
+use crate::LogInError;
+
+/// Where the client certificate and private key are loaded from.
+#[derive(Debug, Clone)]
+pub(crate) enum IdentitySource {
+    /// A PEM file with the certificate chain and the private key.
+    Pem(PathBuf),
+    /// A PKCS#12 (.pfx/.p12) file and its password.
+    Pkcs12(PathBuf, String),
+}
+
+/// A client certificate chain and private key presented to servers that require mutual TLS.
+#[cfg(feature = "pinning")]
+pub(crate) struct ClientIdentity {
+    pub(crate) certs: Vec<CertificateDer<'static>>,
+    pub(crate) key: PrivateKeyDer<'static>,
+}
+
+#[cfg(feature = "pinning")]
+impl ClientIdentity {
+    pub(crate) fn load(source: &IdentitySource) -> Result<Self, LogInError> {
+        match source {
+            IdentitySource::Pem(path) => {
+                let data = read(path)?;
+                let certs = CertificateDer::pem_slice_iter(&data)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|e| tls_error(path, e))?;
+                let key = PrivateKeyDer::from_pem_slice(&data).map_err(|e| tls_error(path, e))?;
+                ClientIdentity::new(path, certs, key)
+            }
+            IdentitySource::Pkcs12(path, password) => {
+                let data = read(path)?;
+                let store = KeyStore::from_pkcs12(&data, password, Pkcs12ImportPolicy::Strict)
+                    .map_err(|e| tls_error(path, e))?;
+                let (_, chain) = store
+                    .private_key_chain()
+                    .ok_or_else(|| tls_error(path, "no private key found"))?;
+                let certs = chain
+                    .certs()
+                    .iter()
+                    .map(|cert| CertificateDer::from(cert.as_der().to_vec()))
+                    .collect();
+                let key =
+                    PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(chain.key().as_der().to_vec()));
+                ClientIdentity::new(path, certs, key)
+            }
+        }
+    }
+
+    fn new(
+        path: &Path,
+        certs: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> Result<Self, LogInError> {
+        if certs.is_empty() {
+            return Err(tls_error(path, "no certificate found"));
+        }
+        Ok(ClientIdentity { certs, key })
+    }
+
+    /// Returns the identity for reqwest's default TLS backend.
+    /// native-tls only loads PKCS#8 keys, which is what PKCS#12 files contain.
+    #[cfg(feature = "native-tls")]
+    pub(crate) fn reqwest_identity(&self) -> Result<reqwest::Identity, LogInError> {
+        let PrivateKeyDer::Pkcs8(key) = &self.key else {
+            return Err(LogInError::TlsConfigError(String::from(
+                "the native-tls backend requires a PKCS#8 private key (BEGIN PRIVATE KEY)",
+            )));
+        };
+        let certs: String = self
+            .certs
+            .iter()
+            .map(|cert| pem_block("CERTIFICATE", cert))
+            .collect();
+        let key = pem_block("PRIVATE KEY", key.secret_pkcs8_der());
+        Ok(reqwest::Identity::from_pkcs8_pem(
+            certs.as_bytes(),
+            key.as_bytes(),
+        )?)
+    }
+
+    /// Returns the identity for reqwest's default TLS backend.
+    #[cfg(not(feature = "native-tls"))]
+    pub(crate) fn reqwest_identity(&self) -> Result<reqwest::Identity, LogInError> {
+        let mut pem: String = self
+            .certs
+            .iter()
+            .map(|cert| pem_block("CERTIFICATE", cert))
+            .collect();
+        let (label, der) = match &self.key {
+            PrivateKeyDer::Pkcs1(key) => ("RSA PRIVATE KEY", key.secret_pkcs1_der()),
+            PrivateKeyDer::Sec1(key) => ("EC PRIVATE KEY", key.secret_sec1_der()),
+            _ => ("PRIVATE KEY", self.key.secret_der()),
+        };
+        pem.push_str(&pem_block(label, der));
+        Ok(reqwest::Identity::from_pem(pem.as_bytes())?)
+    }
+}
+
+/// A client certificate chain and private key presented to servers that require mutual TLS.
+/// Without the `pinning` feature rustls is not available, so the files are loaded by native-tls.
+#[cfg(not(feature = "pinning"))]
+pub(crate) struct ClientIdentity {
+    identity: reqwest::Identity,
+}
+
+#[cfg(not(feature = "pinning"))]
+impl ClientIdentity {
+    pub(crate) fn load(source: &IdentitySource) -> Result<Self, LogInError> {
+        let identity = match source {
+            IdentitySource::Pem(path) => {
+                let data = read(path)?;
+                let data = String::from_utf8(data).map_err(|e| tls_error(path, e))?;
+                let (certs, key) = split_pem(&data);
+                if certs.is_empty() {
+                    return Err(tls_error(path, "no certificate found"));
+                }
+                let key = key.ok_or_else(|| {
+                    tls_error(
+                        path,
+                        "the native-tls backend requires a PKCS#8 private key (BEGIN PRIVATE KEY)",
+                    )
+                })?;
+                reqwest::Identity::from_pkcs8_pem(certs.as_bytes(), key.as_bytes())
+                    .map_err(|e| tls_error(path, e))?
+            }
+            IdentitySource::Pkcs12(path, password) => {
+                reqwest::Identity::from_pkcs12_der(&read(path)?, password)
+                    .map_err(|e| tls_error(path, e))?
+            }
+        };
+        Ok(ClientIdentity { identity })
+    }
+
+    /// Returns the identity for reqwest's default TLS backend.
+    pub(crate) fn reqwest_identity(&self) -> Result<reqwest::Identity, LogInError> {
+        Ok(self.identity.clone())
+    }
+}
+
+/// Splits a PEM file into its certificates and PKCS#8 private key.
+#[cfg(not(feature = "pinning"))]
+fn split_pem(data: &str) -> (String, Option<String>) {
+    let mut certs = String::new();
+    let mut key = None;
+    let mut rest = data;
+    while let Some(start) = rest.find("-----BEGIN ") {
+        let block = &rest[start..];
+        let label_end = block[11..].find("-----").map_or(block.len(), |i| i + 11);
+        let label = &block[11..label_end];
+        let end_marker = format!("-----END {}-----", label);
+        let end = block
+            .find(&end_marker)
+            .map_or(block.len(), |i| i + end_marker.len());
+        match label {
+            "CERTIFICATE" => {
+                certs.push_str(&block[..end]);
+                certs.push('\n');
+            }
+            "PRIVATE KEY" => key = Some(block[..end].to_string()),
+            _ => {}
+        }
+        rest = &block[end..];
+    }
+    (certs, key)
+}
+
+/// Loads the CA certificates from a PEM file, which can contain several, or a DER file.
+pub(crate) fn load_root_certificates(path: &Path) -> Result<Vec<reqwest::Certificate>, LogInError> {
+    let data = read(path)?;
+    if data.iter().all(u8::is_ascii_whitespace) {
+        return Err(tls_error(path, "no certificate found"));
+    }
+
+    let certs = if data.windows(10).any(|w| w == b"-----BEGIN") {
+        reqwest::Certificate::from_pem_bundle(&data)
+    } else {
+        reqwest::Certificate::from_der(&data).map(|cert| vec![cert])
+    }
+    .map_err(|e| tls_error(path, e))?;
+
+    if certs.is_empty() {
+        return Err(tls_error(path, "no certificate found"));
+    }
+    Ok(certs)
+}
+
+#[cfg(feature = "pinning")]
+fn pem_block(label: &str, der: &[u8]) -> String {
+    let encoded = STANDARD.encode(der);
+    let mut block = format!("-----BEGIN {}-----\n", label);
+    for line in encoded.as_bytes().chunks(64) {
+        block.push_str(std::str::from_utf8(line).unwrap_or_default());
+        block.push('\n');
+    }
+    block.push_str(&format!("-----END {}-----\n", label));
+    block
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, LogInError> {
+    fs::read(path).map_err(|e| tls_error(path, e))
+}
+
+fn tls_error(path: &Path, e: impl std::fmt::Display) -> LogInError {
+    LogInError::TlsConfigError(format!("{}: {}", path.display(), e))
+}
@@ -0,0 +1,111 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{utils::files::replace_file, LogInError};
+
+use super::{LoginResponse, Profile};
+
+/// Key used to save a token, made up of the profile name, address and username.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TokenKey {
+    pub profile: String,
+    pub address: String,
+    pub username: String,
+}
+
+impl TokenKey {
+    pub fn new(profile: &Profile, address: &str, username: &str) -> Self {
+        TokenKey {
+            profile: profile.name.clone(),
+            address: address.to_string(),
+            username: username.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for TokenKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}@{}", self.profile, self.username, self.address)
+    }
+}
+
+/// Trait used by `VClientBuilder` to save and reuse tokens between runs.
+/// When a store is set, `build` will reuse a saved token that is still valid, refresh
+/// an expired one, and only fall back to a full login when neither works.
+pub trait TokenStore: Send + Sync {
+    /// Returns the saved login response for the key, if there is one.
+    fn load(&self, key: &TokenKey) -> Result<Option<LoginResponse>, LogInError>;
+
+    /// Saves the login response for the key, replacing any existing one.
+    fn save(&self, key: &TokenKey, login_response: &LoginResponse) -> Result<(), LogInError>;
+
+    /// Removes the saved login response for the key.
+    fn remove(&self, key: &TokenKey) -> Result<(), LogInError>;
+}
+
+/// A token store backed by a JSON file, which can hold tokens for many servers.
+/// On Unix the file is only readable by the owner.
+#[derive(Debug)]
+pub struct FileTokenStore {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileTokenStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FileTokenStore {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Returns the path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read(&self) -> Result<BTreeMap<String, LoginResponse>, LogInError> {
+        match fs::read_to_string(&self.path) {
+            Ok(data) if data.trim().is_empty() => Ok(BTreeMap::new()),
+            Ok(data) => Ok(serde_json::from_str(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+            Err(e) => Err(self.error(e)),
+        }
+    }
+
+    fn write(&self, tokens: &BTreeMap<String, LoginResponse>) -> Result<(), LogInError> {
+        let data = serde_json::to_string_pretty(tokens)?;
+        replace_file(&self.path, data.as_bytes(), true).map_err(|e| self.error(e))
+    }
+
+    fn error(&self, e: std::io::Error) -> LogInError {
+        LogInError::TokenStoreError(format!("{}: {}", self.path.display(), e))
+    }
+}
+
+impl TokenStore for FileTokenStore {
+    fn load(&self, key: &TokenKey) -> Result<Option<LoginResponse>, LogInError> {
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(self.read()?.remove(&key.to_string()))
+    }
+
+    fn save(&self, key: &TokenKey, login_response: &LoginResponse) -> Result<(), LogInError> {
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut tokens = self.read()?;
+        tokens.insert(key.to_string(), login_response.clone());
+        self.write(&tokens)
+    }
+
+    fn remove(&self, key: &TokenKey) -> Result<(), LogInError> {
+        let _lock = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+        let mut tokens = self.read()?;
+        if tokens.remove(&key.to_string()).is_some() {
+            self.write(&tokens)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+use reqwest::{header::HeaderMap, Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::sync::Mutex;
+
+use crate::{utils::trace::event, LogInError};
+
+use super::{api_error::api_error, LoginResponse, Paginator, Poller, Profile, VClientBuilder};
+
+/// How long before the token expires that it will be refreshed.
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+/// An authenticated client for the Veeam REST API, created with `VClientBuilder::build_client`.
+/// It holds the reqwest client, the profile, the address and the current token.
+/// The auth headers are injected on every request and the token is refreshed before it expires.
+/// If the server returns a 401 the client will re-authenticate once and retry the request.
+/// The client is cheap to clone and the clones share the same token, so it can be used
+/// across many tokio tasks.
+#[derive(Clone)]
+pub struct VClient {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    client: reqwest::Client,
+    builder: VClientBuilder,
+    profile: Profile,
+    token: Mutex<TokenState>,
+    logged_out: AtomicBool,
+}
+
+struct TokenState {
+    login_response: LoginResponse,
+    refresh_at: Instant,
+}
+
+impl TokenState {
+    fn new(login_response: LoginResponse) -> Self {
+        // Saved tokens may have been issued some time ago
+        let expires_in = match login_response.expires_at() {
+            Some(expires_at) => expires_at
+                .duration_since(SystemTime::now())
+                .unwrap_or_default(),
+            None => Duration::from_secs(login_response.expires_in.max(0) as u64),
+        };
+        // Short lived tokens are refreshed half way through their lifetime
+        let margin = REFRESH_MARGIN.min(expires_in / 2);
+        TokenState {
+            login_response,
+            refresh_at: Instant::now() + expires_in - margin,
+        }
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if !self.builder.logout_on_drop || *self.logged_out.get_mut() {
+            return;
+        }
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+
+        let client = self.client.clone();
+        let builder = self.builder.clone();
+        let profile = self.profile.clone();
+        let login_response = self.token.get_mut().login_response.clone();
+        handle.spawn(async move {
+            let _ = builder
+                .logout_with(&client, &profile, &login_response)
+                .await;
+        });
+    }
+}
+
+impl VClient {
+    pub(crate) fn new(
+        client: reqwest::Client,
+        builder: VClientBuilder,
+        profile: Profile,
+        login_response: LoginResponse,
+    ) -> Self {
+        VClient {
+            inner: Arc::new(Inner {
+                client,
+                builder,
+                profile,
+                token: Mutex::new(TokenState::new(login_response)),
+                logged_out: AtomicBool::new(false),
+            }),
+        }
+    }
+
+    /// Returns the profile used by the client.
+    pub fn profile(&self) -> &Profile {
+        &self.inner.profile
+    }
+
+    /// Returns the address of the Veeam server.
+    pub fn address(&self) -> &str {
+        &self.inner.builder.address
+    }
+
+    /// Returns the underlying reqwest client, note that this does not add the auth headers.
+    pub fn client(&self) -> &reqwest::Client {
+        &self.inner.client
+    }
+
+    /// Returns the current login response, refreshing it first if it is about to expire.
+    pub async fn login_response(&self) -> Result<LoginResponse, LogInError> {
+        let mut token = self.inner.token.lock().await;
+        self.ensure_fresh(&mut token).await?;
+        Ok(token.login_response.clone())
+    }
+
+    /// Returns the auth headers for the current token, refreshing it first if it is about to expire.
+    pub async fn auth_headers(&self) -> Result<HeaderMap, LogInError> {
+        let login_response = self.login_response().await?;
+        Ok(self
+            .inner
+            .profile
+            .build_auth_headers_from_response(&login_response)?)
+    }
+
+    /// Builds the URL for an endpoint using the profile, e.g. "jobs".
+    pub fn url(&self, end_point: &str) -> Result<String, LogInError> {
+        self.inner
+            .profile
+            .build_url(&self.inner.builder.address, end_point)
+    }
+
+    /// Creates a request for an endpoint, e.g. "jobs". The request should be sent with
+    /// `VClient::send` so that the auth headers are added.
+    pub fn request(&self, method: Method, end_point: &str) -> Result<RequestBuilder, LogInError> {
+        Ok(self.inner.client.request(method, self.url(end_point)?))
+    }
+
+    /// Sends a request with the auth headers added.
+    /// If the server responds with a 401 the client will re-authenticate and retry the request once.
+    /// Transient errors are retried if the builder's retry policy has `api_calls` set.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, LogInError> {
+        self.send_with_retry(request)
+            .await
+            .map_err(|e| self.inner.builder.tls_error(e))
+    }
+
+    async fn send_with_retry(&self, request: RequestBuilder) -> Result<Response, LogInError> {
+        let login_response = self.login_response().await?;
+        let retry = request.try_clone();
+
+        let request = request.headers(
+            self.inner
+                .profile
+                .build_auth_headers_from_response(&login_response)?,
+        );
+        let response = self.inner.builder.send(request, true).await?;
+
+        let retry = match retry {
+            Some(retry) if response.status() == StatusCode::UNAUTHORIZED => retry,
+            _ => return Ok(response),
+        };
+
+        let login_response = self.reauthenticate(&login_response).await?;
+
+        let retry = retry.headers(
+            self.inner
+                .profile
+                .build_auth_headers_from_response(&login_response)?,
+        );
+        self.inner.builder.send(retry, true).await
+    }
+
+    /// Sends a GET request to an endpoint, e.g. "jobs".
+    pub async fn get(&self, end_point: &str) -> Result<Response, LogInError> {
+        self.send(self.request(Method::GET, end_point)?).await
+    }
+
+    /// Sends a GET request to an endpoint and deserializes the JSON response.
+    pub async fn get_json<T: DeserializeOwned>(&self, end_point: &str) -> Result<T, LogInError> {
+        let response = self.get(end_point).await?;
+
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Returns a paginator for a collection endpoint, e.g. the URL from `Profile::build_url` or `VClient::url`.
+    /// See `Paginator` for how each product's endpoints are paged.
+    pub fn paginate(&self, url: &str) -> Paginator {
+        Paginator::new(self.clone(), url)
+    }
+
+    /// Returns a poller for a session or task resource, e.g. the URL of the session returned when a job is started.
+    /// See `Poller` for the products' session and task resources.
+    pub fn poll(&self, url: &str) -> Poller {
+        Poller::new(self.clone(), url)
+    }
+
+    /// Ends the session by calling the product's sign-out endpoint.
+    /// See `VClientBuilder::logout` for the products which support this.
+    pub async fn logout(&self) -> Result<(), LogInError> {
+        let token = self.inner.token.lock().await;
+        let inner = &self.inner;
+        inner
+            .builder
+            .logout_with(&inner.client, &inner.profile, &token.login_response)
+            .await?;
+        inner.logged_out.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Forces the token to be refreshed, falling back to a full login if the refresh fails.
+    pub async fn refresh(&self) -> Result<(), LogInError> {
+        let mut token = self.inner.token.lock().await;
+        self.renew(&mut token).await
+    }
+
+    async fn ensure_fresh(&self, token: &mut TokenState) -> Result<(), LogInError> {
+        if Instant::now() < token.refresh_at {
+            return Ok(());
+        }
+        self.renew(token).await
+    }
+
+    async fn renew(&self, token: &mut TokenState) -> Result<(), LogInError> {
+        let inner = &self.inner;
+        let login_response = match inner
+            .builder
+            .refresh_with(&inner.client, &inner.profile, &token.login_response)
+            .await
+        {
+            Ok(login_response) => login_response,
+            Err(e) => {
+                event!(
+                    DEBUG,
+                    error = e,
+                    "token could not be refreshed, logging in again"
+                );
+                inner
+                    .builder
+                    .authenticate(&inner.client, &inner.profile)
+                    .await?
+            }
+        };
+
+        *token = TokenState::new(login_response);
+        Ok(())
+    }
+
+    /// Logs in again after a 401, unless another task has already replaced the rejected token.
+    async fn reauthenticate(&self, rejected: &LoginResponse) -> Result<LoginResponse, LogInError> {
+        let mut token = self.inner.token.lock().await;
+
+        if token.login_response.access_token == rejected.access_token {
+            event!(INFO, "token was rejected, logging in again");
+            let inner = &self.inner;
+            let login_response = inner
+                .builder
+                .authenticate(&inner.client, &inner.profile)
+                .await?;
+            *token = TokenState::new(login_response);
+        }
+
+        Ok(token.login_response.clone())
+    }
+}
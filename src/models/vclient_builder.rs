@@ -1,33 +1,75 @@
-use once_cell::sync::Lazy;
-use regex::Regex;
-use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE};
-use std::{env, time::Duration};
+use reqwest::{
+    header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE},
+    Method,
+};
+use serde::Serialize;
+use std::{
+    env,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use crate::{check_valid_ip, Creds, LogInError};
+use crate::{utils::trace::event, Creds, LogInError, MfaCreds, RefreshCreds};
 
-use super::{LoginResponse, Profile};
+use super::{
+    api_error::api_error,
+    api_version::VersionDiscovery,
+    mfa::TokenResponse,
+    profile::AuthStyle,
+    tls::{load_root_certificates, ClientIdentity, IdentitySource},
+    Address, ContextFile, CredentialProvider, EnvPassword, KnownServers, LoginResponse,
+    MfaCodeProvider, Profile, RetryPolicy, Secret, ThumbprintAlgorithm, TokenKey, TokenStore,
+    VClient,
+};
 
-static API_VERSION_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"v[0-9]").unwrap());
-static PORT_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"[0-9]{2,}").unwrap());
+#[cfg(feature = "pinning")]
+use super::{
+    pin_verifier::{pinned_tls_config, rejected_certificate},
+    CertificatePin,
+};
+
+/// Saved tokens are refreshed instead of reused when they expire within this margin.
+const TOKEN_REUSE_MARGIN: Duration = Duration::from_secs(60);
 
 /// Returns a reqwest client and a login response struct.
 /// The `VClientBuilder` struct is used to build a reqwest client for Veeam REST API authentication.
 /// It allows for configuration of various parameters such as address, username, insecure connection, timeout, API version, X-API-Version, and port.
 /// It works differently to the VServerBuilder which returned an authenticated reqwest client.
+#[derive(Clone)]
 pub struct VClientBuilder {
-    address: String,
+    pub(crate) address: String,
     username: String,
     insecure: Option<bool>,
     timeout: Option<u64>,
     api_version: Option<String>,
     x_api_version: Option<String>,
     port: Option<String>,
+    credentials: Option<Arc<dyn CredentialProvider>>,
+    token_store: Option<Arc<dyn TokenStore>>,
+    pub(crate) logout_on_drop: bool,
+    mfa: Option<Arc<dyn MfaCodeProvider>>,
+    pins: Vec<(ThumbprintAlgorithm, String)>,
+    known_servers: Option<Arc<KnownServers>>,
+    root_certificates: Vec<PathBuf>,
+    system_roots: bool,
+    identity: Option<IdentitySource>,
+    proxy: Option<String>,
+    proxy_auth: Option<(String, String)>,
+    no_proxy: Option<String>,
+    system_proxy: bool,
+    client: Option<reqwest::Client>,
+    client_builder: Option<Arc<ClientBuilderFn>>,
+    retry: Option<RetryPolicy>,
+    discover_api_version: bool,
 }
 
+type ClientBuilderFn = dyn Fn() -> reqwest::ClientBuilder + Send + Sync;
+
 impl VClientBuilder {
     /// Create a new VServerBuilder
     /// # Arguments
-    /// * `address` - The IP address of the Veeam server
+    /// * `address` - The IP address, hostname or FQDN of the Veeam server, optionally with a port
     /// * `username` - The username to authenticate with
     /// # Returns
     /// A new instance of `VClientBuilder`
@@ -40,15 +82,161 @@ impl VClientBuilder {
             api_version: None,
             x_api_version: None,
             port: None,
+            credentials: None,
+            token_store: None,
+            logout_on_drop: false,
+            mfa: None,
+            pins: Vec::new(),
+            known_servers: None,
+            root_certificates: Vec::new(),
+            system_roots: true,
+            identity: None,
+            proxy: None,
+            proxy_auth: None,
+            no_proxy: None,
+            system_proxy: true,
+            client: None,
+            client_builder: None,
+            retry: None,
+            discover_api_version: false,
         }
     }
 
+    /// Create a builder from a context in the default context file, see `ContextFile`.
+    /// Returns the builder and the profile to build it with.
+    pub fn from_context(name: &str) -> Result<(Self, Profile), LogInError> {
+        ContextFile::load_default()?.builder(name)
+    }
+
+    /// Create a builder from the current context in the default context file, see `ContextFile`.
+    pub fn from_current_context() -> Result<(Self, Profile), LogInError> {
+        ContextFile::load_default()?.current_builder()
+    }
+
     /// Set the Client to use insecure connections
     pub fn insecure(&mut self) -> &mut Self {
         self.insecure = Some(true);
         self
     }
 
+    /// Only accept the server's certificate if its SHA-256 thumbprint matches, e.g. the thumbprint
+    /// shown in the Veeam console. This is a safe alternative to `insecure` for self-signed certificates,
+    /// the chain and hostname are not checked and `insecure` is ignored.
+    /// It can be called more than once to accept any of several certificates, e.g. while one is replaced.
+    /// A certificate which does not match returns `LogInError::CertificatePinMismatch`.
+    /// Requires the `pinning` feature.
+    pub fn pin_certificate_sha256(&mut self, thumbprint: &str) -> &mut Self {
+        self.pins
+            .push((ThumbprintAlgorithm::Sha256, thumbprint.to_string()));
+        self
+    }
+
+    /// Only accept the server's certificate if its SHA-1 thumbprint matches, this is the thumbprint
+    /// shown by Windows and older Veeam consoles. See `pin_certificate_sha256`.
+    /// Requires the `pinning` feature.
+    pub fn pin_certificate_sha1(&mut self, thumbprint: &str) -> &mut Self {
+        self.pins
+            .push((ThumbprintAlgorithm::Sha1, thumbprint.to_string()));
+        self
+    }
+
+    /// Trust the server's certificate the first time it is connected to and record its thumbprint
+    /// in the known servers file, later connections are only accepted if the certificate matches.
+    /// A changed certificate returns `LogInError::CertificateChanged`. Servers are recorded by
+    /// address and port, and explicit pins take precedence over the file.
+    /// Requires the `pinning` feature.
+    pub fn known_servers(&mut self, known_servers: KnownServers) -> &mut Self {
+        self.known_servers = Some(Arc::new(known_servers));
+        self
+    }
+
+    /// Trust the CA certificates in a PEM file, which can contain several, or a DER file,
+    /// e.g. the root of an internal PKI. It can be called more than once.
+    pub fn add_root_certificate(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.root_certificates.push(path.into());
+        self
+    }
+
+    /// Only trust the CA certificates added with `add_root_certificate`, not the system's.
+    pub fn disable_system_roots(&mut self) -> &mut Self {
+        self.system_roots = false;
+        self
+    }
+
+    /// Present a client certificate to servers that require mutual TLS, e.g. a reverse proxy.
+    /// The PEM file must contain the certificate, any intermediates, and the private key.
+    pub fn identity_pem(&mut self, path: impl Into<PathBuf>) -> &mut Self {
+        self.identity = Some(IdentitySource::Pem(path.into()));
+        self
+    }
+
+    /// Present a client certificate from a PKCS#12 (.pfx or .p12) file. See `identity_pem`.
+    pub fn identity_pkcs12(&mut self, path: impl Into<PathBuf>, password: &str) -> &mut Self {
+        self.identity = Some(IdentitySource::Pkcs12(path.into(), password.to_string()));
+        self
+    }
+
+    /// Send all requests through an HTTP proxy, e.g. `http://proxy.corp.local:3128`.
+    /// HTTPS requests are tunnelled with CONNECT. This replaces any proxy set in the environment.
+    pub fn proxy(&mut self, url: &str) -> &mut Self {
+        self.proxy = Some(url.to_string());
+        self
+    }
+
+    /// Set the username and password for the proxy set with `proxy`, sent with basic auth.
+    pub fn proxy_auth(&mut self, username: &str, password: &str) -> &mut Self {
+        self.proxy_auth = Some((username.to_string(), password.to_string()));
+        self
+    }
+
+    /// Set the hosts which are connected to directly instead of through the proxy, as a comma
+    /// separated list in the same format as `NO_PROXY`, e.g. `localhost,.corp.local,10.0.0.0/8`.
+    /// This applies to the proxy set with `proxy`, or the proxies from the environment variables
+    /// along with the hosts in `NO_PROXY`.
+    pub fn no_proxy(&mut self, hosts: &str) -> &mut Self {
+        self.no_proxy = Some(hosts.to_string());
+        self
+    }
+
+    /// Ignore the `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and `NO_PROXY` environment variables,
+    /// which are used by default when `proxy` is not set.
+    pub fn ignore_system_proxy(&mut self) -> &mut Self {
+        self.system_proxy = false;
+        self
+    }
+
+    /// Use an existing reqwest client for the token request and the returned client, e.g. to share
+    /// its connection pool, middleware or user agent. vauth only does the authentication with it,
+    /// so the builder's connection settings such as `insecure`, `timeout` and proxies are not used.
+    /// Certificate pins, known servers, root certificates and client identities cannot be added to
+    /// an existing client, so setting any of them returns `LogInError::TlsConfigError`,
+    /// use `with_client_builder` instead.
+    pub fn with_client(&mut self, client: reqwest::Client) -> &mut Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Create the reqwest client from a builder returned by the function, e.g.
+    /// `|| reqwest::Client::builder().user_agent("backup-report/1.0")`.
+    /// The builder's connection settings are applied on top, the timeout only if it is set.
+    /// The function is called each time a client is needed, e.g. by `refresh` and `logout`.
+    pub fn with_client_builder(
+        &mut self,
+        builder: impl Fn() -> reqwest::ClientBuilder + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.client_builder = Some(Arc::new(builder));
+        self
+    }
+
+    /// Retry login and refresh requests that fail with a transient error, such as a 503 or a refused
+    /// connection while the Veeam services restart. Requests sent with a `VClient` are also retried if
+    /// the policy's `api_calls` is set. If every attempt fails with an error, `LogInError::RetryError` holds each attempt's error,
+    /// and a retryable status on the last attempt is returned as it would be without retries.
+    pub fn retry(&mut self, policy: RetryPolicy) -> &mut Self {
+        self.retry = Some(policy);
+        self
+    }
+
     /// Manually set the timeout for the client; default is 30 seconds
     pub fn timeout(&mut self, value: u64) -> &mut Self {
         self.timeout = Some(value);
@@ -67,16 +255,66 @@ impl VClientBuilder {
         self
     }
 
+    /// Ask the server for its version while building and use the newest API version both the
+    /// server and this library support, instead of the profile's default.
+    /// The profile passed to `build` is updated. A server older than the oldest supported version
+    /// returns `LogInError::UnsupportedServerVersion`.
+    /// This is skipped if `api_version` or `x_api_version` is set, and for products without a
+    /// version endpoint (VBAWS, VBAZURE, VBGCP and Enterprise Manager).
+    pub fn discover_api_version(&mut self) -> &mut Self {
+        self.discover_api_version = true;
+        self
+    }
+
     /// Manually set the port for the client, e.g 1234
     pub fn port(&mut self, value: String) -> &mut Self {
         self.port = Some(value);
         self
     }
 
+    /// Set where the password is read from, by default this is the `VEEAM_API_PASSWORD`
+    /// environment variable. See `StaticPassword`, `EnvPassword`, `FilePassword`,
+    /// `PromptPassword` and `CommandPassword`.
+    pub fn credentials(&mut self, provider: impl CredentialProvider + 'static) -> &mut Self {
+        self.credentials = Some(Arc::new(provider));
+        self
+    }
+
+    /// Set the callback used to get a one-time code when VBR returns a multi-factor authentication
+    /// challenge. Without this an MFA challenge will return `LogInError::MfaRequired`.
+    pub fn mfa(&mut self, provider: impl MfaCodeProvider + 'static) -> &mut Self {
+        self.mfa = Some(Arc::new(provider));
+        self
+    }
+
+    /// Set a store to save tokens to, e.g. `FileTokenStore`.
+    /// When building, a saved token that is still valid is reused without logging in, an expired
+    /// token is refreshed, and a full login is only done if neither works. New tokens are saved.
+    pub fn token_store(&mut self, store: impl TokenStore + 'static) -> &mut Self {
+        self.token_store = Some(Arc::new(store));
+        self
+    }
+
+    /// Log out of the session when the last clone of a `VClient` built by `build_client` is dropped.
+    /// This is best effort, the sign-out request is spawned on the current tokio runtime and
+    /// any error is ignored.
+    pub fn logout_on_drop(&mut self) -> &mut Self {
+        self.logout_on_drop = true;
+        self
+    }
+
     /// Build the reqwest client, this takes a mutable reference to a Profile and will attempt to authenticate to the Veeam REST API.
     /// It will return a tuple with both the client and the login response struct.
     /// The login response struct contains the token and refresh token which you can save for
     /// future use.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            skip_all,
+            err(Display),
+            fields(address = %self.address, username = %self.username, profile = %profile.name)
+        )
+    )]
     pub async fn build(
         &mut self,
         profile: &mut Profile,
@@ -85,102 +323,563 @@ impl VClientBuilder {
             return Err(LogInError::UsernameEmpty);
         }
 
-        let api_pass_res = env::var("VEEAM_API_PASSWORD");
+        Address::parse(&self.address)?;
+        event!(DEBUG, "address is valid");
+
+        if let Some(api_version) = &self.api_version {
+            profile.api_version = api_version.to_string();
+        }
+
+        if let Some(x_api_version) = &self.x_api_version {
+            profile.x_api_version = Some(x_api_version.to_string());
+        }
+
+        if let Some(port) = &self.port {
+            profile.port = port.to_string();
+        }
+
+        let discovery = if self.discover_api_version
+            && self.api_version.is_none()
+            && self.x_api_version.is_none()
+        {
+            VersionDiscovery::for_profile(profile.profile_type)
+        } else {
+            None
+        };
+        // Discovery logs in with the oldest supported version, so it works on a copy of the
+        // profile which only replaces the caller's once the version has been negotiated.
+        let mut negotiated = profile.clone();
+        if let Some(discovery) = &discovery {
+            discovery.use_oldest(&mut negotiated);
+        }
+
+        let client = self.http_client(&negotiated)?;
+        let res_data = self
+            .login(&client, &negotiated)
+            .await
+            .map_err(|e| self.tls_error(e))?;
+
+        if let Some(discovery) = &discovery {
+            self.negotiate_api_version(&client, &mut negotiated, &res_data, discovery)
+                .await
+                .map_err(|e| self.tls_error(e))?;
+        }
+
+        *profile = negotiated;
+        Ok((client, res_data))
+    }
+
+    /// Build a `VClient`, an authenticated client which injects the auth headers on every request
+    /// and refreshes the token before it expires.
+    /// This takes ownership of the Profile as the client keeps it to build URLs and headers.
+    pub async fn build_client(&mut self, mut profile: Profile) -> Result<VClient, LogInError> {
+        let (client, login_response) = self.build(&mut profile).await?;
+
+        Ok(VClient::new(client, self.clone(), profile, login_response))
+    }
+
+    /// Requests the product version and updates the profile to the newest API version it supports.
+    async fn negotiate_api_version(
+        &self,
+        client: &reqwest::Client,
+        profile: &mut Profile,
+        login_response: &LoginResponse,
+        discovery: &VersionDiscovery,
+    ) -> Result<(), LogInError> {
+        let url = profile.build_url(&self.address, discovery.end_point)?;
+        let request = client
+            .get(url)
+            .headers(profile.build_auth_headers_from_response(login_response)?);
+        let response = self.send(request, false).await?;
+
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+
+        let body: serde_json::Value = response.json().await?;
+        let server_version = body
+            .pointer(discovery.pointer)
+            .and_then(serde_json::Value::as_str)
+            .ok_or_else(|| {
+                LogInError::VersionDiscoveryError(format!(
+                    "{} is missing from the {} response",
+                    discovery.pointer, discovery.end_point
+                ))
+            })?;
+
+        profile.negotiate_api_version(server_version)?;
+        event!(
+            INFO,
+            server_version = server_version,
+            api_version = profile.api_version,
+            x_api_version = profile.x_api_version.as_deref().unwrap_or_default(),
+            "negotiated API version"
+        );
+        Ok(())
+    }
+
+    /// Reuses or refreshes a saved token if there is a token store, otherwise logs in.
+    async fn login(
+        &self,
+        client: &reqwest::Client,
+        profile: &Profile,
+    ) -> Result<LoginResponse, LogInError> {
+        if let Some(store) = &self.token_store {
+            let key = TokenKey::new(profile, &self.address, &self.username);
+            if let Some(saved) = store.load(&key)? {
+                if !saved.is_expired(TOKEN_REUSE_MARGIN) {
+                    event!(DEBUG, expires_in = saved.expires_in, "reusing saved token");
+                    return Ok(saved);
+                }
+                match self.refresh_with(client, profile, &saved).await {
+                    Ok(login_response) => return Ok(login_response),
+                    Err(e) => event!(DEBUG, error = e, "saved token could not be refreshed"),
+                }
+            }
+        }
+
+        self.authenticate(client, profile).await
+    }
 
-        let api_pass = match api_pass_res {
-            Ok(r) => r,
-            Err(e) => return Err(LogInError::EnvError(e)),
+    /// Log in to the token endpoint of the profile with the username and password.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub(crate) async fn authenticate(
+        &self,
+        client: &reqwest::Client,
+        profile: &Profile,
+    ) -> Result<LoginResponse, LogInError> {
+        let login_response = self.request_login(client, profile).await?;
+        event!(INFO, expires_in = login_response.expires_in, "logged in");
+        self.save_token(profile, &login_response)?;
+        Ok(login_response)
+    }
+
+    async fn request_login(
+        &self,
+        client: &reqwest::Client,
+        profile: &Profile,
+    ) -> Result<LoginResponse, LogInError> {
+        let api_pass = match &self.credentials {
+            Some(provider) => provider.password()?,
+            None => EnvPassword::default().password()?,
         };
 
         if api_pass.is_empty() {
             return Err(LogInError::PasswordEmpty);
         }
 
-        if self.address.is_empty() {
-            return Err(LogInError::IpAddressEmpty);
+        let auth_url = profile.token_url(&self.address)?;
+
+        if profile.auth_style == AuthStyle::OAuth {
+            event!(
+                DEBUG,
+                token_url = auth_url,
+                auth_style = "OAuth password grant",
+                "requesting token"
+            );
+            let creds = Creds::new(&self.username, &api_pass);
+            return match self
+                .request_token(client, &auth_url, profile, &creds)
+                .await?
+            {
+                TokenResponse::Token(login_response) => Ok(login_response),
+                TokenResponse::Mfa(challenge) => {
+                    event!(INFO, "multi-factor authentication code required");
+                    let provider = self.mfa.as_ref().ok_or(LogInError::MfaRequired)?;
+                    let code = Secret::from(provider.code(&challenge)?);
+                    let creds = MfaCreds::new(&challenge.mfa_token, &code);
+                    self.request_token(client, &auth_url, profile, &creds)
+                        .await?
+                        .into_login_response()
+                }
+            };
         }
 
-        if !check_valid_ip(&self.address) {
-            return Err(LogInError::IpAddressError);
+        event!(
+            DEBUG,
+            token_url = auth_url,
+            auth_style = "basic",
+            "requesting session"
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        headers.insert(CONTENT_LENGTH, "0".parse().unwrap());
+        let request = client
+            .post(auth_url)
+            .basic_auth(&self.username, Some(api_pass.expose_secret()))
+            .headers(headers);
+        let response = self.send(request, false).await?;
+
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
         }
 
-        if let Some(api_version) = &self.api_version {
-            API_VERSION_RE.replace(&profile.url, api_version);
-            profile.api_version = api_version.to_string();
+        let token = response
+            .headers()
+            .get("X-RestSvcSessionId")
+            .ok_or_else(|| LogInError::HeaderMissing("X-RestSvcSessionId".into()))?
+            .to_str()
+            .map_err(|e| LogInError::OtherError(format!("Header to_str error: {}", e)))?
+            .to_string();
+
+        Ok(LoginResponse {
+            access_token: Secret::from(token.clone()),
+            refresh_token: Secret::from(token),
+            expires_in: 900,
+            token_type: String::from(""),
+            issued_at: None,
         }
+        .issued_now())
+    }
 
-        if let Some(x_api_version) = &self.x_api_version {
-            profile.x_api_version = Some(x_api_version.to_string());
+    /// Exchange the refresh token from a previous login for a new login response.
+    /// This uses the OAuth `refresh_token` grant against the profile's token URL, so the
+    /// password does not need to be sent again.
+    /// Enterprise Manager sessions cannot be refreshed and will return `LogInError::NoRefreshToken`.
+    pub async fn refresh(
+        &self,
+        profile: &Profile,
+        login_response: &LoginResponse,
+    ) -> Result<LoginResponse, LogInError> {
+        Address::parse(&self.address)?;
+
+        let client = self.http_client(profile)?;
+        self.refresh_with(&client, profile, login_response)
+            .await
+            .map_err(|e| self.tls_error(e))
+    }
+
+    /// Sends the refresh token grant using an existing reqwest client.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub(crate) async fn refresh_with(
+        &self,
+        client: &reqwest::Client,
+        profile: &Profile,
+        login_response: &LoginResponse,
+    ) -> Result<LoginResponse, LogInError> {
+        if profile.auth_style == AuthStyle::Session || login_response.refresh_token.is_empty() {
+            return Err(LogInError::NoRefreshToken);
         }
 
-        if let Some(port) = &self.port {
-            PORT_RE.replace(&profile.url, port.as_str());
-            profile.port = port.to_string();
+        let auth_url = profile.token_url(&self.address)?;
+        let creds = RefreshCreds::new(&login_response.refresh_token);
+        event!(DEBUG, token_url = auth_url, "refreshing token");
+
+        let login_response = self
+            .request_token(client, &auth_url, profile, &creds)
+            .await?
+            .into_login_response()?;
+        event!(
+            INFO,
+            expires_in = login_response.expires_in,
+            "token refreshed"
+        );
+        self.save_token(profile, &login_response)?;
+        Ok(login_response)
+    }
+
+    /// Ends the session by calling the product's sign-out endpoint, e.g. `DELETE /api/logonSessions/{id}`
+    /// for Enterprise Manager and `POST /api/oauth2/logout` for VBR. The token is also removed
+    /// from the token store.
+    /// Products without a sign-out endpoint return `LogInError::Unsupported`, their tokens are
+    /// only ended when they expire.
+    pub async fn logout(
+        &self,
+        profile: &Profile,
+        login_response: &LoginResponse,
+    ) -> Result<(), LogInError> {
+        let client = self.http_client(profile)?;
+        self.logout_with(&client, profile, login_response)
+            .await
+            .map_err(|e| self.tls_error(e))
+    }
+
+    /// Sends the sign-out request using an existing reqwest client.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub(crate) async fn logout_with(
+        &self,
+        client: &reqwest::Client,
+        profile: &Profile,
+        login_response: &LoginResponse,
+    ) -> Result<(), LogInError> {
+        if let Some(store) = &self.token_store {
+            store.remove(&TokenKey::new(profile, &self.address, &self.username))?;
+        }
+
+        let logout_url = profile
+            .logout_url(&self.address, login_response.access_token.expose_secret())?
+            .ok_or_else(|| {
+                LogInError::Unsupported(format!(
+                    "{} does not have a sign-out endpoint",
+                    profile.name
+                ))
+            })?;
+
+        let method = if profile.auth_style == AuthStyle::Session {
+            Method::DELETE
+        } else {
+            Method::POST
+        };
+
+        let request = client
+            .request(method, logout_url)
+            .headers(profile.build_auth_headers_from_response(login_response)?)
+            .header(CONTENT_LENGTH, "0");
+        let response = self.send_request(request, false, true).await?;
+        event!(DEBUG, status = response.status(), "sign-out response");
+
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+
+        event!(INFO, "logged out");
+        Ok(())
+    }
+
+    /// Saves the token to the token store, if there is one.
+    fn save_token(
+        &self,
+        profile: &Profile,
+        login_response: &LoginResponse,
+    ) -> Result<(), LogInError> {
+        match &self.token_store {
+            Some(store) => store.save(
+                &TokenKey::new(profile, &self.address, &self.username),
+                login_response,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the reason the server's certificate was rejected, e.g. `LogInError::CertificatePinMismatch`,
+    /// if a request failed because of it, otherwise the error is returned unchanged.
+    pub(crate) fn tls_error(&self, error: LogInError) -> LogInError {
+        #[cfg(feature = "pinning")]
+        return rejected_certificate(error);
+        #[cfg(not(feature = "pinning"))]
+        error
+    }
+
+    /// Creates the underlying reqwest client from the builder settings.
+    fn http_client(&self, profile: &Profile) -> Result<reqwest::Client, LogInError> {
+        if let Some(client) = &self.client {
+            // Silently connecting without the certificate checks would be worse than failing
+            if !self.pins.is_empty()
+                || self.known_servers.is_some()
+                || !self.root_certificates.is_empty()
+                || self.identity.is_some()
+            {
+                return Err(LogInError::TlsConfigError(String::from(
+                    "certificate pins, known servers, root certificates and client identities cannot be applied to a client set with `with_client`, use `with_client_builder`",
+                )));
+            }
+            return Ok(client.clone());
         }
 
         let insecure = self.insecure.unwrap_or(false);
-        let timeout_val = self.timeout.unwrap_or(30);
 
-        let client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(timeout_val))
-            .danger_accept_invalid_certs(insecure)
-            .build()?;
+        let (mut builder, timeout) = match &self.client_builder {
+            Some(client_builder) => (client_builder(), self.timeout),
+            None => (reqwest::Client::builder(), Some(self.timeout.unwrap_or(30))),
+        };
 
-        let mut headers = HeaderMap::new();
-        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
-        let auth_url = format!("https://{}{}", self.address, profile.url);
+        if let Some(timeout) = timeout {
+            builder = builder.timeout(Duration::from_secs(timeout));
+        }
 
-        let response: reqwest::Response = if profile.name != "ENTMAN" {
-            let creds = Creds::new(&self.username, &api_pass);
-            let creds_urlenc = serde_urlencoded::to_string(&creds)?;
-            if let Some(x_api_version) = &profile.x_api_version {
-                headers.insert("X-Api-Version", HeaderValue::from_str(x_api_version)?);
+        if !self.system_roots {
+            builder = builder.tls_built_in_root_certs(false);
+        }
+
+        if !self.system_proxy {
+            builder = builder.no_proxy();
+        }
+
+        if let Some(url) = &self.proxy {
+            let mut proxy = reqwest::Proxy::all(url)?;
+            if let Some((username, password)) = &self.proxy_auth {
+                proxy = proxy.basic_auth(username, password);
             }
-            // headers.insert("X-Api-Version", profile.x_api_version.parse().unwrap());
-            headers.insert(
-                CONTENT_TYPE,
-                HeaderValue::from_static("application/x-www-form-urlencoded"),
-            );
-            client
-                .post(auth_url)
-                .body(creds_urlenc)
-                .headers(headers)
-                .send()
-                .await?
-        } else {
-            headers.insert(CONTENT_LENGTH, "0".parse().unwrap());
-            client
-                .post(auth_url)
-                .basic_auth(self.username.clone(), Some(api_pass))
-                .headers(headers)
-                .send()
-                .await?
+            if let Some(hosts) = &self.no_proxy {
+                proxy = proxy.no_proxy(reqwest::NoProxy::from_string(hosts));
+            }
+            builder = builder.proxy(proxy);
+        } else if let Some(hosts) = self.no_proxy.as_ref().filter(|_| self.system_proxy) {
+            // reqwest only reads NO_PROXY for the environment proxies, so they are set up here
+            for proxy in env_proxies(hosts)? {
+                builder = builder.proxy(proxy);
+            }
+        }
+
+        for path in &self.root_certificates {
+            for certificate in load_root_certificates(path)? {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        let mut identity = self
+            .identity
+            .as_ref()
+            .map(ClientIdentity::load)
+            .transpose()?;
+
+        if let Some(tls_config) = self.pinned_tls_config(profile, &mut identity)? {
+            return Ok(builder.use_preconfigured_tls(tls_config).build()?);
+        }
+
+        if let Some(identity) = identity {
+            builder = builder.identity(identity.reqwest_identity()?);
+        }
+        Ok(builder.danger_accept_invalid_certs(insecure).build()?)
+    }
+
+    /// Returns the rustls config which checks the pins or the known servers file, if either is set.
+    #[cfg(feature = "pinning")]
+    fn pinned_tls_config(
+        &self,
+        profile: &Profile,
+        identity: &mut Option<ClientIdentity>,
+    ) -> Result<Option<rustls::ClientConfig>, LogInError> {
+        let pins = self
+            .pins
+            .iter()
+            .map(|(algorithm, thumbprint)| CertificatePin::new(*algorithm, thumbprint))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let tls_config = match &self.known_servers {
+            _ if !pins.is_empty() => pinned_tls_config(pins, None, identity.take())?,
+            Some(known_servers) => {
+                let server = Address::parse(&self.address)?.authority(&profile.port);
+                // Checks the file can be read before connecting
+                known_servers.get(&server)?;
+                pinned_tls_config(
+                    Vec::new(),
+                    Some((known_servers.clone(), server)),
+                    identity.take(),
+                )?
+            }
+            None => return Ok(None),
+        };
+        Ok(Some(tls_config))
+    }
+
+    /// Pinned connections use rustls, which is only available with the `pinning` feature.
+    #[cfg(not(feature = "pinning"))]
+    fn pinned_tls_config(
+        &self,
+        _profile: &Profile,
+        _identity: &mut Option<ClientIdentity>,
+    ) -> Result<Option<std::convert::Infallible>, LogInError> {
+        if !self.pins.is_empty() || self.known_servers.is_some() {
+            return Err(LogInError::TlsConfigError(String::from(
+                "certificate pinning and known servers require the `pinning` feature",
+            )));
+        }
+        Ok(None)
+    }
+
+    /// Sends a request, retrying it if there is a retry policy. API calls are only retried
+    /// if the policy's `api_calls` is set.
+    pub(crate) async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+        api_call: bool,
+    ) -> Result<reqwest::Response, LogInError> {
+        self.send_request(request, api_call, false).await
+    }
+
+    /// Sends a request as `send` does. With `redact_url` the URL is removed from request errors
+    /// before they are logged or returned, as Enterprise Manager puts the session id in the
+    /// logout URL.
+    async fn send_request(
+        &self,
+        request: reqwest::RequestBuilder,
+        api_call: bool,
+        redact_url: bool,
+    ) -> Result<reqwest::Response, LogInError> {
+        let map_error = |e: reqwest::Error| {
+            let e = if redact_url { e.without_url() } else { e };
+            self.tls_error(LogInError::ReqwestError(e))
         };
 
-        let res_data: LoginResponse;
-
-        if response.status().is_success() {
-            if profile.name == "ENTMAN" {
-                let token = response
-                    .headers()
-                    .get("X-RestSvcSessionId")
-                    .ok_or_else(|| LogInError::HeaderMissing("X-RestSvcSessionId".into()))?
-                    .to_str()
-                    .map_err(|e| LogInError::OtherError(format!("Header to_str error: {}", e)))?
-                    .to_string();
-                res_data = LoginResponse {
-                    access_token: token.clone(),
-                    refresh_token: token,
-                    expires_in: 900,
-                    token_type: String::from(""),
-                }
-            } else {
-                res_data = response.json().await?
+        let start = Instant::now();
+        let response = match &self.retry {
+            Some(policy) if policy.api_calls || !api_call => {
+                policy.send(request, map_error).await?
             }
-        } else {
-            return Err(LogInError::StatusCodeError(response.status()));
+            _ => request.send().await.map_err(map_error)?,
+        };
+        event!(
+            DEBUG,
+            status = response.status(),
+            elapsed_ms = start.elapsed().as_millis(),
+            "response received"
+        );
+        Ok(response)
+    }
+
+    /// Sends a form encoded OAuth token request and parses the login response or MFA challenge.
+    async fn request_token<T: Serialize>(
+        &self,
+        client: &reqwest::Client,
+        auth_url: &str,
+        profile: &Profile,
+        form: &T,
+    ) -> Result<TokenResponse, LogInError> {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static("application/json"));
+        if let Some(x_api_version) = &profile.x_api_version {
+            headers.insert("X-Api-Version", HeaderValue::from_str(x_api_version)?);
         }
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/x-www-form-urlencoded"),
+        );
 
-        Ok((client, res_data))
+        let request = client
+            .post(auth_url)
+            .body(serde_urlencoded::to_string(form)?)
+            .headers(headers);
+        let response = self.send(request, false).await?;
+
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+
+        Ok(match response.json::<TokenResponse>().await? {
+            TokenResponse::Token(login_response) => {
+                TokenResponse::Token(login_response.issued_now())
+            }
+            challenge => challenge,
+        })
+    }
+}
+
+/// Returns the proxies from the `HTTPS_PROXY`, `HTTP_PROXY` and `ALL_PROXY` environment variables,
+/// or their lower case names, which skip the hosts in `NO_PROXY` and the extra hosts.
+fn env_proxies(hosts: &str) -> Result<Vec<reqwest::Proxy>, LogInError> {
+    let var = |name: &str| {
+        env::var(name)
+            .or_else(|_| env::var(name.to_ascii_lowercase()))
+            .ok()
+            .filter(|value| !value.is_empty())
+    };
+    let no_proxy = match var("NO_PROXY") {
+        Some(env_hosts) => format!("{},{}", env_hosts, hosts),
+        None => hosts.to_string(),
+    };
+
+    let mut proxies = Vec::new();
+    for name in ["HTTPS_PROXY", "HTTP_PROXY", "ALL_PROXY"] {
+        let Some(url) = var(name) else {
+            continue;
+        };
+        let proxy = match name {
+            "HTTPS_PROXY" => reqwest::Proxy::https(&url)?,
+            "HTTP_PROXY" => reqwest::Proxy::http(&url)?,
+            _ => reqwest::Proxy::all(&url)?,
+        };
+        proxies.push(proxy.no_proxy(reqwest::NoProxy::from_string(&no_proxy)));
     }
+    Ok(proxies)
 }
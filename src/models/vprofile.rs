@@ -1,4 +1,4 @@
-use crate::models::profile::ProfileType;
+use crate::models::profile::{AuthStyle, ProfileType};
 
 use super::Profile;
 
@@ -22,66 +22,108 @@ pub enum VProfile {
 
 /// Implementation of methods for the VProfile enum.
 impl VProfile {
+    /// Returns the profile with the name, e.g. `VBR` or `vb365`, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "VBR" => Some(VProfile::VBR),
+            "VB365" => Some(VProfile::VB365),
+            "VBAWS" => Some(VProfile::VBAWS),
+            "VBAZURE" => Some(VProfile::VBAZURE),
+            "VBGCP" => Some(VProfile::VBGCP),
+            "VONE" => Some(VProfile::VONE),
+            "ENTMAN" => Some(VProfile::ENTMAN),
+            _ => None,
+        }
+    }
+
     /// Get the profile data for the Veeam REST API.
     /// This method returns a `Profile` struct containing the profile information
-    /// such as name, URL, port, API version, and X-API-Version.
+    /// such as name, port, base and token paths, API version, and X-API-Version.
     pub fn profile_data(&self) -> Profile {
         match self {
             VProfile::VB365 => Profile {
                 profile_type: ProfileType::VB365,
                 name: "VB365".to_string(),
-                url: ":4443/v7/Token".to_string(),
+                scheme: "https".to_string(),
                 port: "4443".to_string(),
+                base_path: "".to_string(),
+                token_path: "/v7/Token".to_string(),
+                logout_path: None,
                 api_version: "v8".to_string(),
                 x_api_version: None,
+                auth_style: AuthStyle::OAuth,
             },
             VProfile::VBAWS => Profile {
                 profile_type: ProfileType::VBAWS,
                 name: "VBAWS".to_string(),
-                url: ":11005/api/v1/token".to_string(),
+                scheme: "https".to_string(),
                 port: "11005".to_string(),
+                base_path: "/api".to_string(),
+                token_path: "/api/{api_version}/token".to_string(),
+                logout_path: None,
                 api_version: "v1".to_string(),
                 x_api_version: Some("1.7-rev0".to_string()),
+                auth_style: AuthStyle::OAuth,
             },
             VProfile::VBR => Profile {
                 profile_type: ProfileType::VBR,
                 name: "VBR".to_string(),
-                url: ":9419/api/oauth2/token".to_string(),
+                scheme: "https".to_string(),
                 port: "9419".to_string(),
+                base_path: "/api".to_string(),
+                token_path: "/api/oauth2/token".to_string(),
+                logout_path: Some("/api/oauth2/logout".to_string()),
                 api_version: "v1".to_string(),
                 x_api_version: Some("1.2-rev1".to_string()),
+                auth_style: AuthStyle::OAuth,
             },
             VProfile::VBAZURE => Profile {
                 profile_type: ProfileType::VBAZURE,
                 name: "VBAZURE".to_string(),
-                url: "/api/oauth2/token".to_string(),
+                scheme: "https".to_string(),
                 port: "".to_string(),
+                base_path: "/api".to_string(),
+                token_path: "/api/oauth2/token".to_string(),
+                logout_path: None,
                 api_version: "v8".to_string(),
                 x_api_version: None,
+                auth_style: AuthStyle::OAuth,
             },
             VProfile::VBGCP => Profile {
                 profile_type: ProfileType::VBGCP,
                 name: "VBGCP".to_string(),
-                url: ":13140/api/v1/token".to_string(),
+                scheme: "https".to_string(),
                 port: "13140".to_string(),
+                base_path: "/api".to_string(),
+                token_path: "/api/{api_version}/token".to_string(),
+                logout_path: None,
                 api_version: "v1".to_string(),
                 x_api_version: Some("1.4-rev0".to_string()),
+                auth_style: AuthStyle::OAuth,
             },
             VProfile::VONE => Profile {
                 profile_type: ProfileType::VONE,
                 name: "VONE".to_string(),
-                url: ":1239/api/token".to_string(),
+                scheme: "https".to_string(),
                 port: "1239".to_string(),
+                base_path: "/api".to_string(),
+                token_path: "/api/token".to_string(),
+                logout_path: None,
                 api_version: "v2.2".to_string(),
                 x_api_version: None,
+                auth_style: AuthStyle::OAuth,
             },
             VProfile::ENTMAN => Profile {
                 profile_type: ProfileType::ENTMAN,
                 name: "ENTMAN".to_string(),
-                url: ":9398/api/sessionMngr/?v=latest".to_string(),
+                scheme: "https".to_string(),
                 port: "9398".to_string(),
+                base_path: "/api".to_string(),
+                token_path: "/api/sessionMngr/?v=latest".to_string(),
+                logout_path: Some("/api/logonSessions/{session_id}".to_string()),
                 api_version: "".to_string(),
                 x_api_version: None,
+                auth_style: AuthStyle::Session,
             },
         }
     }
@@ -1,10 +1,9 @@
-use regex::Regex;
 use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE};
 use std::{env, time::Duration};
 
-use crate::{check_valid_ip, Creds, LogInError};
+use crate::{Creds, LogInError};
 
-use super::{LoginResponse, Profile};
+use super::{profile::AuthStyle, Address, LoginResponse, Profile, Secret};
 
 /// The `VServerBuilder` struct is used to build a reqwest client for Veeam REST API authentication.
 /// This struct is deprecated and will be removed in future versions. Use `VClientBuilder` instead.
@@ -12,7 +11,6 @@ use super::{LoginResponse, Profile};
     since = "1.0.0",
     note = "Use VClientBuilder instead. VServerBuilder will be removed in future versions."
 )]
-
 pub struct VServerBuilder {
     address: String,
     username: String,
@@ -83,7 +81,7 @@ impl VServerBuilder {
         let api_pass_res = env::var("VEEAM_API_PASSWORD");
 
         let api_pass = match api_pass_res {
-            Ok(r) => r,
+            Ok(r) => Secret::from(r),
             Err(e) => return Err(LogInError::EnvError(e)),
         };
 
@@ -91,17 +89,9 @@ impl VServerBuilder {
             return Err(LogInError::PasswordEmpty);
         }
 
-        if self.address.is_empty() {
-            return Err(LogInError::IpAddressEmpty);
-        }
-
-        if !check_valid_ip(&self.address) {
-            return Err(LogInError::IpAddressError);
-        }
+        Address::parse(&self.address)?;
 
         if let Some(api_version) = &self.api_version {
-            let re = Regex::new("v[0-9]").unwrap();
-            re.replace(&profile.url, api_version);
             profile.api_version = api_version.to_string();
         }
 
@@ -110,8 +100,6 @@ impl VServerBuilder {
         }
 
         if let Some(port) = &self.port {
-            let re = Regex::new("[0-9]{2,}").unwrap();
-            re.replace(&profile.url, port.as_str());
             profile.port = port.to_string();
         }
 
@@ -125,9 +113,9 @@ impl VServerBuilder {
 
         let mut headers = HeaderMap::new();
         headers.insert(ACCEPT, "application/json".parse().unwrap());
-        let auth_url = format!("https://{}{}", self.address, profile.url);
+        let auth_url = profile.token_url(&self.address)?;
 
-        let response: reqwest::Response = if profile.name != "ENTMAN" {
+        let response: reqwest::Response = if profile.auth_style == AuthStyle::OAuth {
             let creds = Creds::new(&self.username, &api_pass);
             let creds_urlenc = serde_urlencoded::to_string(&creds).unwrap();
             if let Some(x_api_version) = &profile.x_api_version {
@@ -150,7 +138,7 @@ impl VServerBuilder {
             headers.insert(CONTENT_LENGTH, "0".parse().unwrap());
             client
                 .post(auth_url)
-                .basic_auth(self.username.clone(), Some(api_pass))
+                .basic_auth(self.username.clone(), Some(api_pass.expose_secret()))
                 .headers(headers)
                 .send()
                 .await?
@@ -159,7 +147,7 @@ impl VServerBuilder {
         let res_data: LoginResponse;
 
         if response.status().is_success() {
-            if profile.name == "ENTMAN" {
+            if profile.auth_style == AuthStyle::Session {
                 let token = response
                     .headers()
                     .get("X-RestSvcSessionId")
@@ -168,10 +156,11 @@ impl VServerBuilder {
                     .unwrap()
                     .to_string();
                 res_data = LoginResponse {
-                    access_token: token.clone(),
-                    refresh_token: token,
+                    access_token: Secret::from(token.clone()),
+                    refresh_token: Secret::from(token),
                     expires_in: 900,
                     token_type: String::from(""),
+                    issued_at: None,
                 }
             } else {
                 res_data = response.json().await?
@@ -180,16 +169,16 @@ impl VServerBuilder {
             return Err(LogInError::StatusCodeError(response.status()));
         }
 
-        let bearer: String = if profile.name != *"ENTMAN" {
-            format!("Bearer {}", res_data.access_token.as_str().trim())
+        let bearer: String = if profile.auth_style == AuthStyle::OAuth {
+            format!("Bearer {}", res_data.access_token.expose_secret().trim())
         } else {
-            res_data.access_token.as_str().trim().to_owned()
+            res_data.access_token.expose_secret().trim().to_owned()
         };
 
         let mut req_header = HeaderMap::new();
         req_header.insert(ACCEPT, "application/json".parse().unwrap());
         req_header.insert(CONTENT_TYPE, "application/json".parse().unwrap());
-        if profile.name == *"ENTMAN" {
+        if profile.auth_style == AuthStyle::Session {
             req_header.insert("X-RestSvcSessionId", bearer.parse().unwrap());
         } else {
             req_header.insert("Authorization", bearer.parse().unwrap());
@@ -3,25 +3,75 @@ use std::env;
 use reqwest::header::InvalidHeaderValue;
 use thiserror::Error;
 
+use crate::models::{ApiErrorBody, ThumbprintAlgorithm};
+
 /// LogInError is used to return errors from the build method.
 #[derive(Error, Debug)]
 pub enum LogInError {
     #[error("The VEEAM_API_PASSWORD environmental variable is missing")]
     EnvError(#[from] env::VarError),
-    #[error("IP Address is not valid")]
-    IpAddressError,
+    #[error("Address is not valid: {0}")]
+    InvalidAddress(String),
     #[error("Username cannot be empty")]
     UsernameEmpty,
     #[error("Password cannot be empty")]
     PasswordEmpty,
-    #[error("IP Address cannot be empty")]
-    IpAddressEmpty,
+    #[error("Credential error: {0}")]
+    CredentialError(String),
+    #[error("Address cannot be empty")]
+    AddressEmpty,
+    #[error("The server requires a multi-factor authentication code, set a callback with VClientBuilder::mfa")]
+    MfaRequired,
     #[error("No refresh token")]
     NoRefreshToken,
+    #[error("Token store error: {0}")]
+    TokenStoreError(String),
+    #[error("Certificate pin is not valid: {0}")]
+    InvalidCertificatePin(String),
+    #[error("The server certificate does not match the pinned thumbprint, the server presented {algorithm} thumbprint {presented}")]
+    CertificatePinMismatch {
+        algorithm: ThumbprintAlgorithm,
+        presented: String,
+    },
+    #[error("The certificate of {server} has changed, the recorded thumbprint is {expected} and the server presented {presented}")]
+    CertificateChanged {
+        server: String,
+        expected: String,
+        presented: String,
+    },
+    #[error("TLS configuration error: {0}")]
+    TlsConfigError(String),
+    #[error("Known servers error: {0}")]
+    KnownServersError(String),
+    #[error("Failed after {} attempts, the last error was: {}", .0.len(), .0.last().map(ToString::to_string).unwrap_or_default())]
+    RetryError(Vec<LogInError>),
+    #[error("Pagination error: {0}")]
+    PaginationError(String),
+    #[error("The session did not finish within {0:?}")]
+    PollTimeout(std::time::Duration),
+    #[error("Polling was cancelled")]
+    PollCancelled,
+    #[error("{product} {version} is not supported, the minimum version is {minimum}")]
+    UnsupportedServerVersion {
+        product: String,
+        version: String,
+        minimum: String,
+    },
+    #[error("API version discovery error: {0}")]
+    VersionDiscoveryError(String),
+    #[error("Profile file error: {0}")]
+    ProfileFileError(String),
+    #[error("Context file error: {0}")]
+    ContextError(String),
     #[error("Error in sending request `{0:?}`")]
     ReqwestError(#[from] reqwest::Error),
     #[error("Status Code Error `{0}`")]
     StatusCodeError(reqwest::StatusCode),
+    #[error("API Error `{status}`: {body}")]
+    ApiError {
+        status: reqwest::StatusCode,
+        body: Box<ApiErrorBody>,
+    },
     #[error("Header value error: {0}")]
     HeaderValueError(#[from] InvalidHeaderValue),
     #[error("Header missing: {0}")]
@@ -30,6 +80,8 @@ pub enum LogInError {
     SerdeUrlEncodedError(#[from] serde_urlencoded::ser::Error),
     #[error("Serde JSON error: {0}")]
     SerdeJsonError(#[from] serde_json::Error),
+    #[error("Not supported: {0}")]
+    Unsupported(String),
     #[error("Other Error `{0}`")]
     OtherError(String),
     #[error("Anyhow Error `{0}`")]
@@ -0,0 +1,55 @@
+//! Writing the token and known servers files.
+
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Replaces the file with the contents, creating its directory if needed.
+/// The contents are written to a tmp file with a unique name in the same directory and renamed
+/// over the file, so concurrent writers do not overwrite each other's tmp file, the rename stays
+/// on one filesystem and readers never see a partly written file.
+/// A private file is created so only the owner can read it, before anything is written to it.
+pub(crate) fn replace_file(path: &Path, contents: &[u8], private: bool) -> io::Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+
+    let tmp_path = tmp_path(path);
+    let result = write_new(&tmp_path, contents, private).and_then(|_| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(format!(
+        ".{}.{}.tmp",
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    path.with_file_name(file_name)
+}
+
+fn write_new(path: &Path, contents: &[u8], private: bool) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
@@ -1,24 +1,25 @@
 use super::error::LogInError;
-use crate::models::profile::Profile;
+use crate::models::{address::Address, profile::Profile};
 use reqwest::header::HeaderMap;
 
 pub fn check_valid_ip(address: &str) -> bool {
     address.parse::<std::net::IpAddr>().is_ok()
 }
 
+/// Checks if the address is a valid IP address, hostname or FQDN, with an optional port.
+pub fn check_valid_address(address: &str) -> bool {
+    Address::parse(address).is_ok()
+}
+
 /// Helper function to build the url for the reqwest client
 ///
 /// # Arguments
 ///
-/// * `address` - The IP address of the Veeam server
+/// * `address` - The IP address or hostname of the Veeam server
 /// * `end_point` - The API endpoint to be called, but not including the API version e.g. /api/v1/backups > backups
 /// * `profile` - The profile to be used for the request
-pub fn build_url(
-    address: &String,
-    end_point: &String,
-    profile: &Profile,
-) -> Result<String, LogInError> {
-    return profile.build_url(address, end_point)
+pub fn build_url(address: &str, end_point: &str, profile: &Profile) -> Result<String, LogInError> {
+    profile.build_url(address, end_point)
 }
 
 /// Helper function to build Auth Headers, this is useful for when you still have a valid token
@@ -28,6 +29,9 @@ pub fn build_url(
 /// * `profile` - The profile to be used for the request
 /// # Returns
 /// A HeaderMap containing the necessary headers for authentication
-pub fn build_auth_headers(token: &String, profile: &Profile) -> Result<HeaderMap, reqwest::header::InvalidHeaderValue> {
-    return profile.build_auth_headers(token)
+pub fn build_auth_headers(
+    token: &str,
+    profile: &Profile,
+) -> Result<HeaderMap, reqwest::header::InvalidHeaderValue> {
+    profile.build_auth_headers(token)
 }
@@ -1,6 +1,8 @@
 pub mod error;
+pub(crate) mod files;
 pub mod helpers;
+pub(crate) mod trace;
 
 pub use error::LogInError;
-pub use helpers::check_valid_ip;
 pub use helpers::{build_auth_headers, build_url};
+pub use helpers::{check_valid_address, check_valid_ip};
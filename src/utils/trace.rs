@@ -0,0 +1,31 @@
+//! Logging for the `tracing` feature.
+//!
+//! Passwords, MFA codes, access tokens and refresh tokens must never be passed to `event!`
+//! or recorded in a span. Logout URLs are also left out as Enterprise Manager puts the
+//! session id in the path.
+
+/// Emits a tracing event at the level, e.g. `event!(DEBUG, status = response.status(), "token response")`.
+/// Field values are recorded with their `Display` implementation.
+/// Without the `tracing` feature this compiles to nothing, the field values are only referenced
+/// so that variables used for logging are not reported as unused.
+#[cfg(feature = "tracing")]
+macro_rules! event {
+    ($level:ident, $($field:ident = $value:expr,)* $message:literal) => {
+        tracing::event!(
+            tracing::Level::$level,
+            $($field = tracing::field::display(&$value),)*
+            $message
+        )
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! event {
+    ($level:ident, $($field:ident = $value:expr,)* $message:literal) => {
+        if false {
+            $(let _ = &$value;)*
+        }
+    };
+}
+
+pub(crate) use event;
@@ -6,6 +6,7 @@ use vauth::models::vprofile::VProfile;
 use vauth::VClientBuilder;
 
 #[tokio::test]
+#[ignore = "requires a live Veeam server configured in .env"]
 async fn test_entman_with_request() {
     dotenvy::dotenv().unwrap();
     let mut profile = VProfile::ENTMAN.profile_data();
@@ -26,6 +27,7 @@ async fn test_entman_with_request() {
 }
 
 #[tokio::test]
+#[ignore = "requires a live Veeam server configured in .env"]
 async fn test_vbr_with_request() {
     dotenvy::dotenv().unwrap();
     let mut profile = VProfile::VBR.profile_data();
@@ -48,6 +50,7 @@ async fn test_vbr_with_request() {
 }
 
 #[tokio::test]
+#[ignore = "requires a live Veeam server configured in .env"]
 async fn test_vb365_with_request() {
     dotenvy::dotenv().unwrap();
     let mut profile = VProfile::VB365.profile_data();
@@ -58,13 +61,15 @@ async fn test_vb365_with_request() {
 
     let (client, res) = vclient.insecure().build(&mut profile).await.unwrap();
 
-    let mut json_file = File::create(&"token.json".to_string()).unwrap();
+    let mut json_file = File::create("token.json").unwrap();
     let token_string = serde_json::to_string_pretty(&res).unwrap();
     json_file.write_all(token_string.as_bytes()).unwrap();
 
     let url = profile.build_url(&address, &String::from("Jobs")).unwrap();
 
-    let auth_headers = profile.build_auth_headers(&res.access_token).unwrap();
+    let auth_headers = profile
+        .build_auth_headers(res.access_token.expose_secret())
+        .unwrap();
 
     let response = client.get(&url).headers(auth_headers).send().await.unwrap();
 
@@ -72,6 +77,7 @@ async fn test_vb365_with_request() {
 }
 
 #[tokio::test]
+#[ignore = "requires a live Veeam server configured in .env"]
 async fn test_vb365_use_token() {
     dotenvy::dotenv().unwrap();
     let profile = VProfile::VB365.profile_data();
@@ -94,7 +100,7 @@ async fn test_vb365_use_token() {
     let url = profile.build_url(&address, &String::from("Jobs")).unwrap();
 
     let headers = profile
-        .build_auth_headers(&login_response.access_token)
+        .build_auth_headers(login_response.access_token.expose_secret())
         .unwrap();
 
     let response = client.get(&url).headers(headers).send().await.unwrap();
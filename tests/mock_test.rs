@@ -0,0 +1,1440 @@
+use futures_util::TryStreamExt;
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use vauth::mock::{MockRequest, MockResponse, MockServer};
+#[cfg(feature = "pinning")]
+use vauth::{CertificatePin, KnownServers, ThumbprintAlgorithm};
+use vauth::{
+    FileTokenStore, LogInError, MfaChallenge, Page, RetryPolicy, SessionResult, StaticPassword,
+    VClient, VClientBuilder, VProfile,
+};
+
+fn builder(server: &MockServer) -> VClientBuilder {
+    let mut builder = VClientBuilder::new(server.address(), &server.username());
+    builder
+        .insecure()
+        .credentials(StaticPassword::new(&server.password()));
+    builder
+}
+
+#[tokio::test]
+async fn test_mock_login_all_profiles() {
+    for v_profile in [
+        VProfile::VBR,
+        VProfile::VB365,
+        VProfile::VBAWS,
+        VProfile::VBAZURE,
+        VProfile::VBGCP,
+        VProfile::VONE,
+        VProfile::ENTMAN,
+    ] {
+        let server = MockServer::builder(v_profile).start().await;
+        let mut profile = server.profile();
+
+        let (client, login_response) = builder(&server).build(&mut profile).await.unwrap();
+        assert!(!login_response.access_token.is_empty());
+        assert_eq!(server.login_count(), 1);
+
+        let end_point = profile.build_url(server.address(), "jobs").unwrap();
+        let path = reqwest::Url::parse(&end_point).unwrap().path().to_string();
+        server.route(Method::GET, &path, |_| {
+            MockResponse::json(StatusCode::OK, json!({ "data": [] }))
+        });
+
+        let headers = profile
+            .build_auth_headers_from_response(&login_response)
+            .unwrap();
+        let response = client
+            .get(&end_point)
+            .headers(headers)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{}", profile.name);
+    }
+}
+
+#[tokio::test]
+async fn test_mock_bad_password() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+
+    let result = VClientBuilder::new(server.address(), &server.username())
+        .insecure()
+        .credentials(StaticPassword::new("wrong"))
+        .build(&mut server.profile())
+        .await;
+
+    match result {
+        Err(LogInError::ApiError { status, body }) => {
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert_eq!(body.error.as_deref(), Some("invalid_grant"));
+        }
+        other => panic!("expected an ApiError, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn test_mock_refresh_and_logout() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let mut profile = server.profile();
+    let mut builder = builder(&server);
+
+    let (_client, login_response) = builder.build(&mut profile).await.unwrap();
+    let refreshed = builder.refresh(&profile, &login_response).await.unwrap();
+    assert_ne!(refreshed.access_token, login_response.access_token);
+    assert_eq!(server.refresh_count(), 1);
+
+    // The old refresh token has been used
+    assert!(builder.refresh(&profile, &login_response).await.is_err());
+
+    builder.logout(&profile, &refreshed).await.unwrap();
+    assert_eq!(server.logout_count(), 1);
+}
+
+#[tokio::test]
+async fn test_mock_entman_logout() {
+    let server = MockServer::builder(VProfile::ENTMAN).start().await;
+    let client = builder(&server)
+        .build_client(server.profile())
+        .await
+        .unwrap();
+
+    client.logout().await.unwrap();
+    assert_eq!(server.logout_count(), 1);
+
+    let request = server.requests().pop().unwrap();
+    assert_eq!(request.method, Method::DELETE);
+    assert!(request.path.starts_with("/api/logonSessions/"));
+}
+
+#[tokio::test]
+async fn test_mock_vclient_reauthenticates_on_401() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    server.route(Method::GET, "/api/v1/jobs", |_| {
+        MockResponse::json(StatusCode::OK, json!({ "data": [] }))
+    });
+
+    let client = builder(&server)
+        .build_client(server.profile())
+        .await
+        .unwrap();
+
+    server.revoke_access_tokens();
+    let jobs: Value = client.get_json("jobs").await.unwrap();
+    assert_eq!(jobs, json!({ "data": [] }));
+    assert_eq!(server.login_count(), 2);
+    assert_eq!(server.refresh_count(), 0);
+}
+
+#[tokio::test]
+async fn test_mock_vclient_refreshes_before_expiry() {
+    let server = MockServer::builder(VProfile::VONE)
+        .expires_in(2)
+        .start()
+        .await;
+    server.route(Method::GET, "/api/v2.2/alarms", |_| {
+        MockResponse::json(StatusCode::OK, json!([]))
+    });
+
+    let client = builder(&server)
+        .build_client(server.profile())
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let _: Value = client.get_json("alarms").await.unwrap();
+    assert_eq!(server.refresh_count(), 1);
+    assert_eq!(server.login_count(), 1);
+}
+
+#[tokio::test]
+async fn test_mock_vclient_shared_across_tasks() {
+    let server = MockServer::builder(VProfile::VBR)
+        .expires_in(2)
+        .start()
+        .await;
+    server.route(Method::GET, "/api/v1/jobs", |_| {
+        MockResponse::json(StatusCode::OK, json!({ "data": [] }))
+    });
+
+    let client = builder(&server)
+        .build_client(server.profile())
+        .await
+        .unwrap();
+
+    let get_jobs = |client: VClient| {
+        tokio::spawn(async move { client.get_json::<Value>("jobs").await.unwrap() })
+    };
+
+    // The clones share one token, so it is only refreshed once
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let tasks: Vec<_> = (0..8).map(|_| get_jobs(client.clone())).collect();
+    for task in tasks {
+        assert_eq!(task.await.unwrap(), json!({ "data": [] }));
+    }
+    assert_eq!(server.refresh_count(), 1);
+    assert_eq!(server.login_count(), 1);
+
+    // and a rejected token is only replaced by one of them
+    server.revoke_access_tokens();
+    let tasks: Vec<_> = (0..8).map(|_| get_jobs(client.clone())).collect();
+    for task in tasks {
+        assert_eq!(task.await.unwrap(), json!({ "data": [] }));
+    }
+    assert_eq!(server.refresh_count(), 1);
+    assert_eq!(server.login_count(), 2);
+}
+
+#[tokio::test]
+async fn test_mock_token_store_reuse() {
+    let server = MockServer::builder(VProfile::VB365).start().await;
+    let path = std::env::temp_dir().join(format!("vauth-mock-tokens-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    for _ in 0..2 {
+        builder(&server)
+            .token_store(FileTokenStore::new(&path))
+            .build(&mut server.profile())
+            .await
+            .unwrap();
+    }
+    assert_eq!(server.login_count(), 1);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn test_mock_mfa() {
+    let server = MockServer::builder(VProfile::VBR)
+        .mfa_code("123456")
+        .start()
+        .await;
+
+    let result = builder(&server).build(&mut server.profile()).await;
+    assert!(matches!(result, Err(LogInError::MfaRequired)));
+
+    let (_client, login_response) = builder(&server)
+        .mfa(|_challenge: &MfaChallenge| Ok(String::from("123456")))
+        .build(&mut server.profile())
+        .await
+        .unwrap();
+    assert!(!login_response.access_token.is_empty());
+
+    // The mock checks the fields against TokenLoginSpec rather than what the library sends
+    let response = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap()
+        .post(format!("https://{}/api/oauth2/token", server.address()))
+        .form(&[("grant_type", "mfa"), ("mfa_token", "x"), ("otp", "123456")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_mock_fail_next() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    server.fail_next(
+        StatusCode::FORBIDDEN,
+        json!({ "errorCode": "AccessDenied", "message": "Account is locked" }),
+    );
+
+    match builder(&server).build(&mut server.profile()).await {
+        Err(LogInError::ApiError { status, body }) => {
+            assert_eq!(status, StatusCode::FORBIDDEN);
+            assert_eq!(body.error_code.as_deref(), Some("AccessDenied"));
+        }
+        other => panic!("expected an ApiError, got {:?}", other.map(|_| ())),
+    }
+
+    assert!(builder(&server).build(&mut server.profile()).await.is_ok());
+}
+
+#[cfg(feature = "pinning")]
+#[tokio::test]
+async fn test_mock_certificate_pin() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let sha256 = ThumbprintAlgorithm::Sha256.thumbprint(server.certificate_der());
+    let sha1 = ThumbprintAlgorithm::Sha1.thumbprint(server.certificate_der());
+
+    VClientBuilder::new(server.address(), &server.username())
+        .credentials(StaticPassword::new(&server.password()))
+        .pin_certificate_sha256(&sha256)
+        .build(&mut server.profile())
+        .await
+        .unwrap();
+
+    VClientBuilder::new(server.address(), &server.username())
+        .credentials(StaticPassword::new(&server.password()))
+        .pin_certificate_sha1(&sha1.to_lowercase())
+        .build(&mut server.profile())
+        .await
+        .unwrap();
+
+    let result = VClientBuilder::new(server.address(), &server.username())
+        .credentials(StaticPassword::new(&server.password()))
+        .pin_certificate_sha256(&"00".repeat(32))
+        .build(&mut server.profile())
+        .await;
+
+    match result {
+        Err(LogInError::CertificatePinMismatch {
+            algorithm,
+            presented,
+        }) => {
+            assert_eq!(algorithm, ThumbprintAlgorithm::Sha256);
+            assert_eq!(presented, sha256);
+        }
+        other => panic!("expected a pin mismatch, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!(server.login_count(), 2);
+}
+
+#[cfg(feature = "pinning")]
+#[tokio::test]
+async fn test_mock_certificate_pin_concurrent() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let mut builder = builder(&server);
+    builder.pin_certificate_sha256(&"00".repeat(32));
+
+    // Each failed handshake returns its own mismatch
+    let builds: Vec<_> = (0..4)
+        .map(|_| {
+            let mut builder = builder.clone();
+            tokio::spawn(async move { builder.build(&mut VProfile::VBR.profile_data()).await })
+        })
+        .collect();
+    for build in builds {
+        let result = build.await.unwrap().map(|_| ());
+        assert!(
+            matches!(result, Err(LogInError::CertificatePinMismatch { .. })),
+            "expected a pin mismatch, got {:?}",
+            result
+        );
+    }
+
+    // and other connection errors are not reported as a certificate failure
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let closed = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    let result = VClientBuilder::new(&closed, &server.username())
+        .credentials(StaticPassword::new(&server.password()))
+        .pin_certificate_sha256(&"00".repeat(32))
+        .build(&mut VProfile::VBR.profile_data())
+        .await;
+    assert!(matches!(result, Err(LogInError::ReqwestError(_))));
+}
+
+#[cfg(feature = "pinning")]
+#[tokio::test]
+async fn test_mock_known_servers() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let path = std::env::temp_dir().join(format!("vauth-mock-known-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let sha256 = ThumbprintAlgorithm::Sha256.thumbprint(server.certificate_der());
+
+    // Trusted and recorded on first use, then accepted as it matches
+    for _ in 0..2 {
+        VClientBuilder::new(server.address(), &server.username())
+            .credentials(StaticPassword::new(&server.password()))
+            .known_servers(KnownServers::new(&path))
+            .build(&mut server.profile())
+            .await
+            .unwrap();
+    }
+    let recorded = KnownServers::new(&path).get(server.address()).unwrap();
+    assert_eq!(recorded.unwrap().thumbprint, sha256);
+
+    // A recorded thumbprint is not replaced
+    let changed = CertificatePin::new(ThumbprintAlgorithm::Sha256, &"00".repeat(32)).unwrap();
+    let known_servers = KnownServers::new(&path);
+    assert!(matches!(
+        known_servers.add(server.address(), &changed),
+        Err(LogInError::CertificateChanged { .. })
+    ));
+    known_servers.remove(server.address()).unwrap();
+    known_servers.add(server.address(), &changed).unwrap();
+
+    let result = VClientBuilder::new(server.address(), &server.username())
+        .credentials(StaticPassword::new(&server.password()))
+        .known_servers(KnownServers::new(&path))
+        .build(&mut server.profile())
+        .await;
+
+    match result {
+        Err(LogInError::CertificateChanged {
+            server: address,
+            expected,
+            presented,
+        }) => {
+            assert_eq!(address, server.address());
+            assert_eq!(expected, changed.thumbprint);
+            assert_eq!(presented, sha256);
+        }
+        other => panic!(
+            "expected a changed certificate, got {:?}",
+            other.map(|_| ())
+        ),
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "pinning")]
+#[tokio::test]
+async fn test_mock_known_servers_changed_concurrent() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let path = std::env::temp_dir().join(format!(
+        "vauth-mock-known-concurrent-{}",
+        std::process::id()
+    ));
+    let changed = CertificatePin::new(ThumbprintAlgorithm::Sha256, &"00".repeat(32)).unwrap();
+    KnownServers::new(&path)
+        .add(server.address(), &changed)
+        .unwrap();
+
+    let mut builder = builder(&server);
+    builder.known_servers(KnownServers::new(&path));
+
+    // Each failed handshake returns its own changed certificate error
+    let builds: Vec<_> = (0..4)
+        .map(|_| {
+            let mut builder = builder.clone();
+            tokio::spawn(async move { builder.build(&mut VProfile::VBR.profile_data()).await })
+        })
+        .collect();
+    for build in builds {
+        let result = build.await.unwrap().map(|_| ());
+        assert!(
+            matches!(result, Err(LogInError::CertificateChanged { .. })),
+            "expected a changed certificate, got {:?}",
+            result
+        );
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+/// Forwards connections to the address in the target, so the server behind an address can change.
+#[cfg(feature = "pinning")]
+async fn switching_forwarder(target: Arc<Mutex<String>>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        while let Ok((mut inbound, _)) = listener.accept().await {
+            let target = target.lock().unwrap().clone();
+            tokio::spawn(async move {
+                let mut outbound = TcpStream::connect(target).await.unwrap();
+                let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+            });
+        }
+    });
+    address
+}
+
+#[cfg(feature = "pinning")]
+#[tokio::test]
+async fn test_mock_known_servers_checked_on_reconnect() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let impostor = MockServer::builder(VProfile::VBR).start().await;
+    let path =
+        std::env::temp_dir().join(format!("vauth-mock-known-reconnect-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let target = Arc::new(Mutex::new(server.address().to_string()));
+    let address = switching_forwarder(target.clone()).await;
+    let mut profile = server.profile();
+    profile.port = address.rsplit(':').next().unwrap().to_string();
+
+    // Every request opens a new connection, so each one is verified
+    let client = VClientBuilder::new(&address, &server.username())
+        .credentials(StaticPassword::new(&server.password()))
+        .with_client_builder(|| reqwest::Client::builder().pool_max_idle_per_host(0))
+        .known_servers(KnownServers::new(&path))
+        .build_client(profile)
+        .await
+        .unwrap();
+    let recorded = KnownServers::new(&path).get(&address).unwrap().unwrap();
+    assert!(recorded.matches(server.certificate_der()));
+
+    *target.lock().unwrap() = impostor.address().to_string();
+    let result = client.get_json::<Value>("jobs").await;
+    assert!(
+        matches!(result, Err(LogInError::CertificateChanged { ref expected, .. }) if *expected == recorded.thumbprint),
+        "expected a changed certificate, got {:?}",
+        result
+    );
+    // The recorded thumbprint is kept
+    let kept = KnownServers::new(&path).get(&address).unwrap().unwrap();
+    assert_eq!(kept, recorded);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(not(feature = "pinning"))]
+#[tokio::test]
+async fn test_mock_pinning_requires_feature() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let result = builder(&server)
+        .pin_certificate_sha256(&"00".repeat(32))
+        .build(&mut server.profile())
+        .await;
+    assert!(matches!(result, Err(LogInError::TlsConfigError(_))));
+    assert_eq!(server.login_count(), 0);
+}
+
+fn temp_file(name: &str, contents: impl AsRef<[u8]>) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("vauth-mock-{}-{}", std::process::id(), name));
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[tokio::test]
+async fn test_mock_root_certificate() {
+    let server = MockServer::builder(VProfile::VB365).start().await;
+    let ca_path = temp_file("ca.pem", server.ca_certificate_pem());
+
+    let result = VClientBuilder::new(server.address(), &server.username())
+        .credentials(StaticPassword::new(&server.password()))
+        .build(&mut server.profile())
+        .await;
+    assert!(matches!(result, Err(LogInError::ReqwestError(_))));
+
+    VClientBuilder::new(server.address(), &server.username())
+        .credentials(StaticPassword::new(&server.password()))
+        .add_root_certificate(&ca_path)
+        .disable_system_roots()
+        .build(&mut server.profile())
+        .await
+        .unwrap();
+
+    let result = VClientBuilder::new(server.address(), &server.username())
+        .add_root_certificate(temp_file("empty.pem", ""))
+        .build(&mut server.profile())
+        .await;
+    assert!(matches!(result, Err(LogInError::TlsConfigError(_))));
+
+    std::fs::remove_file(&ca_path).unwrap();
+}
+
+#[tokio::test]
+async fn test_mock_client_identity() {
+    let server = MockServer::builder(VProfile::VBR)
+        .require_client_certificate()
+        .start()
+        .await;
+    let pem_path = temp_file("client.pem", server.client_identity_pem());
+    let p12_path = temp_file("client.p12", server.client_identity_pkcs12("secret"));
+
+    assert!(builder(&server).build(&mut server.profile()).await.is_err());
+
+    builder(&server)
+        .identity_pem(&pem_path)
+        .build(&mut server.profile())
+        .await
+        .unwrap();
+
+    builder(&server)
+        .identity_pkcs12(&p12_path, "secret")
+        .build(&mut server.profile())
+        .await
+        .unwrap();
+
+    // Pinned connections present the identity with rustls
+    #[cfg(feature = "pinning")]
+    VClientBuilder::new(server.address(), &server.username())
+        .credentials(StaticPassword::new(&server.password()))
+        .pin_certificate_sha256(&ThumbprintAlgorithm::Sha256.thumbprint(server.certificate_der()))
+        .identity_pkcs12(&p12_path, "secret")
+        .build(&mut server.profile())
+        .await
+        .unwrap();
+
+    let result = builder(&server)
+        .identity_pkcs12(&p12_path, "wrong")
+        .build(&mut server.profile())
+        .await;
+    assert!(matches!(result, Err(LogInError::TlsConfigError(_))));
+
+    std::fs::remove_file(&pem_path).unwrap();
+    std::fs::remove_file(&p12_path).unwrap();
+}
+
+/// A minimal HTTP CONNECT proxy which requires basic auth, returning its address and
+/// the targets it tunnelled to.
+async fn connect_proxy() -> (String, Arc<Mutex<Vec<String>>>) {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    let targets = Arc::new(Mutex::new(Vec::new()));
+    let tunnelled = targets.clone();
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let tunnelled = tunnelled.clone();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                let mut request_line = String::new();
+                stream.read_line(&mut request_line).await.unwrap();
+                let mut authorized = false;
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).await.unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        // "proxy-user:proxy-pass"
+                        authorized |= name.eq_ignore_ascii_case("proxy-authorization")
+                            && value.trim() == "Basic cHJveHktdXNlcjpwcm94eS1wYXNz";
+                    }
+                }
+
+                let mut stream = stream.into_inner();
+                if !authorized {
+                    let _ = stream
+                        .write_all(b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n")
+                        .await;
+                    return;
+                }
+
+                let target = request_line.split_whitespace().nth(1).unwrap().to_string();
+                tunnelled.lock().unwrap().push(target.clone());
+                let mut upstream = TcpStream::connect(target).await.unwrap();
+                stream
+                    .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+                    .await
+                    .unwrap();
+                let _ = tokio::io::copy_bidirectional(&mut stream, &mut upstream).await;
+            });
+        }
+    });
+
+    (address, targets)
+}
+
+#[tokio::test]
+async fn test_mock_proxy() {
+    let server = MockServer::builder(VProfile::VB365).start().await;
+    server.route(Method::GET, "/v8/Jobs", |_| {
+        MockResponse::json(StatusCode::OK, json!([]))
+    });
+    let (proxy, targets) = connect_proxy().await;
+
+    let client = builder(&server)
+        .proxy(&proxy)
+        .proxy_auth("proxy-user", "proxy-pass")
+        .build_client(server.profile())
+        .await
+        .unwrap();
+    let _: Value = client.get_json("Jobs").await.unwrap();
+    assert_eq!(targets.lock().unwrap().first().unwrap(), server.address());
+
+    let result = builder(&server)
+        .proxy(&proxy)
+        .build(&mut server.profile())
+        .await;
+    assert!(matches!(result, Err(LogInError::ReqwestError(_))));
+
+    // Direct connections bypass the proxy, which would reject them without credentials
+    builder(&server)
+        .proxy(&proxy)
+        .no_proxy("127.0.0.1")
+        .ignore_system_proxy()
+        .build(&mut server.profile())
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_mock_with_client() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .user_agent("shared-client/1.0")
+        .build()
+        .unwrap();
+
+    VClientBuilder::new(server.address(), &server.username())
+        .credentials(StaticPassword::new(&server.password()))
+        .with_client(client)
+        .build(&mut server.profile())
+        .await
+        .unwrap();
+
+    builder(&server)
+        .with_client_builder(|| reqwest::Client::builder().user_agent("client-builder/1.0"))
+        .build(&mut server.profile())
+        .await
+        .unwrap();
+
+    let user_agents: Vec<_> = server
+        .requests()
+        .iter()
+        .map(|request| request.headers["user-agent"].to_str().unwrap().to_string())
+        .collect();
+    assert_eq!(user_agents, ["shared-client/1.0", "client-builder/1.0"]);
+}
+
+#[tokio::test]
+async fn test_mock_with_client_rejects_certificate_settings() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let mut profile = server.profile();
+    let (client, login_response) = builder(&server).build(&mut profile).await.unwrap();
+
+    let mut pinned = builder(&server);
+    pinned
+        .with_client(client.clone())
+        .pin_certificate_sha256(&"00".repeat(32));
+    let mut rooted = builder(&server);
+    rooted
+        .with_client(client)
+        .add_root_certificate("/etc/pki/corp-root.pem");
+
+    for builder in [&mut pinned, &mut rooted] {
+        let result = builder.build(&mut profile).await.map(|_| ());
+        assert!(matches!(result, Err(LogInError::TlsConfigError(_))));
+        let result = builder.refresh(&profile, &login_response).await.map(|_| ());
+        assert!(matches!(result, Err(LogInError::TlsConfigError(_))));
+        let result = builder.logout(&profile, &login_response).await;
+        assert!(matches!(result, Err(LogInError::TlsConfigError(_))));
+    }
+    assert_eq!(server.login_count(), 1);
+    assert_eq!(server.requests().len(), 1);
+}
+
+fn retry_policy() -> RetryPolicy {
+    RetryPolicy {
+        initial_backoff: Duration::from_millis(10),
+        jitter: false,
+        ..RetryPolicy::default()
+    }
+}
+
+#[tokio::test]
+async fn test_mock_retry() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    for _ in 0..2 {
+        server.fail_next(
+            StatusCode::SERVICE_UNAVAILABLE,
+            json!({ "errorCode": "ServiceUnavailable", "message": "Service is starting" }),
+        );
+    }
+
+    builder(&server)
+        .retry(retry_policy())
+        .build(&mut server.profile())
+        .await
+        .unwrap();
+    assert_eq!(server.requests().len(), 3);
+    assert_eq!(server.login_count(), 1);
+
+    // The errors from every attempt are returned, including the last status
+    for _ in 0..3 {
+        server.fail_next(StatusCode::BAD_GATEWAY, json!({}));
+    }
+    match builder(&server)
+        .retry(retry_policy())
+        .build(&mut server.profile())
+        .await
+    {
+        Err(LogInError::RetryError(errors)) => {
+            assert_eq!(errors.len(), 3);
+            assert!(errors.iter().all(|e| matches!(e,
+                LogInError::ApiError { status, .. } if *status == StatusCode::BAD_GATEWAY)));
+        }
+        other => panic!("expected a RetryError, got {:?}", other.map(|_| ())),
+    }
+
+    server.fail_next(StatusCode::SERVICE_UNAVAILABLE, json!({}));
+    let result = builder(&server)
+        .retry(RetryPolicy {
+            max_attempts: 1,
+            ..retry_policy()
+        })
+        .build(&mut server.profile())
+        .await;
+    assert!(
+        matches!(result, Err(LogInError::ApiError { status, .. }) if status == StatusCode::SERVICE_UNAVAILABLE)
+    );
+
+    // Errors which are not transient are returned straight away
+    server.fail_next(StatusCode::FORBIDDEN, json!({}));
+    let result = builder(&server)
+        .retry(retry_policy())
+        .build(&mut server.profile())
+        .await;
+    assert!(matches!(result, Err(LogInError::ApiError { .. })));
+    assert_eq!(server.requests().len(), 8);
+
+    // Sign-out requests are retried like logins
+    let mut profile = server.profile();
+    let mut builder = builder(&server);
+    builder.retry(retry_policy());
+    let (_client, login_response) = builder.build(&mut profile).await.unwrap();
+    server.fail_next(StatusCode::SERVICE_UNAVAILABLE, json!({}));
+    builder.logout(&profile, &login_response).await.unwrap();
+    assert_eq!(server.logout_count(), 1);
+}
+
+#[tokio::test]
+async fn test_mock_retry_api_calls() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    server.route(Method::GET, "/api/v1/jobs", |_| {
+        MockResponse::json(StatusCode::OK, json!({ "data": [] }))
+    });
+
+    let client = builder(&server)
+        .retry(retry_policy())
+        .build_client(server.profile())
+        .await
+        .unwrap();
+    server.fail_next(StatusCode::SERVICE_UNAVAILABLE, json!({}));
+    let response = client.get("jobs").await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let client = builder(&server)
+        .retry(RetryPolicy {
+            api_calls: true,
+            ..retry_policy()
+        })
+        .build_client(server.profile())
+        .await
+        .unwrap();
+    server.fail_next(StatusCode::SERVICE_UNAVAILABLE, json!({}));
+    let jobs: Value = client.get_json("jobs").await.unwrap();
+    assert_eq!(jobs, json!({ "data": [] }));
+}
+
+#[tokio::test]
+async fn test_mock_retry_connection_refused() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+
+    let mut profile = VProfile::VBR.profile_data();
+    profile.port = port.to_string();
+    let result = VClientBuilder::new("127.0.0.1", "admin")
+        .insecure()
+        .credentials(StaticPassword::new("password"))
+        .retry(retry_policy())
+        .build(&mut profile)
+        .await;
+    match result {
+        Err(LogInError::RetryError(errors)) => assert_eq!(errors.len(), 3),
+        other => panic!("expected a RetryError, got {:?}", other.map(|_| ())),
+    }
+}
+
+/// Collects the formatted tracing output.
+#[cfg(feature = "tracing")]
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<u8>>>);
+
+#[cfg(feature = "tracing")]
+impl std::io::Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tracing")]
+#[tokio::test]
+async fn test_mock_tracing_redacts_secrets() {
+    use vauth::Secret;
+
+    use tracing_subscriber::{
+        filter::{LevelFilter, Targets},
+        layer::SubscriberExt,
+        util::SubscriberInitExt,
+    };
+
+    let capture = Capture::default();
+    let writer = capture.clone();
+    let _guard = tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_writer(move || writer.clone()))
+        .with(Targets::new().with_target("vauth", LevelFilter::TRACE))
+        .set_default();
+
+    let mut secrets = Vec::new();
+
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    server.route(Method::GET, "/api/v1/jobs", |_| {
+        MockResponse::json(StatusCode::OK, json!({ "data": [] }))
+    });
+    let client = builder(&server)
+        .build_client(server.profile())
+        .await
+        .unwrap();
+    secrets.push(Secret::new(server.password()));
+    let login_response = client.login_response().await.unwrap();
+    secrets.push(login_response.access_token);
+    secrets.push(login_response.refresh_token);
+
+    server.revoke_access_tokens();
+    let _: Value = client.get_json("jobs").await.unwrap();
+    client.refresh().await.unwrap();
+    let login_response = client.login_response().await.unwrap();
+    secrets.push(login_response.access_token);
+    secrets.push(login_response.refresh_token);
+    client.logout().await.unwrap();
+
+    let server = MockServer::builder(VProfile::ENTMAN).start().await;
+    let client = builder(&server)
+        .build_client(server.profile())
+        .await
+        .unwrap();
+    secrets.push(client.login_response().await.unwrap().access_token);
+    client.logout().await.unwrap();
+
+    // A sign-out request which is refused and retried does not log the session id in the URL
+    let mut profile = server.profile();
+    let (_, login_response) = builder(&server).build(&mut profile).await.unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let closed = listener.local_addr().unwrap().to_string();
+    drop(listener);
+    let result = VClientBuilder::new(&closed, &server.username())
+        .insecure()
+        .retry(retry_policy())
+        .logout(&profile, &login_response)
+        .await;
+    match &result {
+        Err(LogInError::RetryError(errors)) => assert_eq!(errors.len(), 3),
+        other => panic!("expected a RetryError, got {:?}", other),
+    }
+    let session_id = login_response.access_token.expose_secret();
+    assert!(!format!("{:?}", result).contains(session_id));
+    secrets.push(login_response.access_token);
+
+    let output = String::from_utf8(capture.0.lock().unwrap().clone()).unwrap();
+    for expected in [
+        "address is valid",
+        "token_url=https://",
+        "auth_style=OAuth password grant",
+        "auth_style=basic",
+        "status=200 OK",
+        "elapsed_ms=",
+        "expires_in=",
+        "token was rejected",
+        "token refreshed",
+        "logged out",
+        "request failed, retrying",
+    ] {
+        assert!(
+            output.contains(expected),
+            "{} missing from:\n{}",
+            expected,
+            output
+        );
+    }
+    for secret in secrets {
+        let secret = secret.expose_secret();
+        assert!(!output.contains(secret), "{} found in:\n{}", secret, output);
+    }
+}
+
+/// Returns the path the mock server needs to route for an endpoint.
+fn endpoint_path(client: &VClient, end_point: &str) -> String {
+    reqwest::Url::parse(&client.url(end_point).unwrap())
+        .unwrap()
+        .path()
+        .to_string()
+}
+
+fn query_number(request: &MockRequest, key: &str) -> usize {
+    request.query[key].parse().unwrap()
+}
+
+#[tokio::test]
+async fn test_mock_paginate_vbr() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let client = builder(&server)
+        .build_client(server.profile())
+        .await
+        .unwrap();
+    server.route(Method::GET, &endpoint_path(&client, "jobs"), |request| {
+        let skip = query_number(request, "skip");
+        let limit = query_number(request, "limit");
+        let data: Vec<usize> = (skip..(skip + limit).min(7)).collect();
+        MockResponse::json(
+            StatusCode::OK,
+            json!({ "data": data, "pagination": { "total": 7, "count": data.len(), "skip": skip, "limit": limit } }),
+        )
+    });
+
+    let mut paginator = client.paginate(&client.url("jobs?typeFilter=Backup").unwrap());
+    paginator.page_size(3).concurrency(2);
+    assert_eq!(paginator.total().await.unwrap(), Some(7));
+
+    let items: Vec<usize> = paginator.items::<usize>().try_collect().await.unwrap();
+    assert_eq!(items, (0..7).collect::<Vec<_>>());
+
+    let pages: Vec<Page<usize>> = paginator.pages::<usize>().try_collect().await.unwrap();
+    assert_eq!(pages.len(), 3);
+    assert_eq!(pages[0].total, Some(7));
+
+    let request = server.requests().pop().unwrap();
+    assert_eq!(request.query["typeFilter"], "Backup");
+}
+
+#[tokio::test]
+async fn test_mock_paginate_capped_page_size() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let client = builder(&server)
+        .build_client(server.profile())
+        .await
+        .unwrap();
+    let short_page = Arc::new(AtomicBool::new(false));
+    let short = short_page.clone();
+    server.route(Method::GET, &endpoint_path(&client, "jobs"), move |request| {
+        let skip = query_number(request, "skip");
+        let mut limit = query_number(request, "limit").min(2);
+        if skip > 0 && short.load(Ordering::SeqCst) {
+            limit = 1;
+        }
+        let data: Vec<usize> = (skip..(skip + limit).min(7)).collect();
+        MockResponse::json(
+            StatusCode::OK,
+            json!({ "data": data, "pagination": { "total": 7, "count": data.len(), "skip": skip, "limit": limit } }),
+        )
+    });
+
+    let mut paginator = client.paginate(&client.url("jobs").unwrap());
+    paginator.page_size(5).concurrency(3);
+    let items: Vec<usize> = paginator.items::<usize>().try_collect().await.unwrap();
+    assert_eq!(items, (0..7).collect::<Vec<_>>());
+    let request = server.requests().pop().unwrap();
+    assert_eq!(request.query["limit"], "2");
+
+    short_page.store(true, Ordering::SeqCst);
+    let result: Result<Vec<usize>, _> = paginator.items::<usize>().try_collect().await;
+    assert!(matches!(result, Err(LogInError::PaginationError(_))));
+}
+
+#[tokio::test]
+async fn test_mock_paginate_vb365() {
+    let server = MockServer::builder(VProfile::VB365).start().await;
+    let client = builder(&server)
+        .build_client(server.profile())
+        .await
+        .unwrap();
+    let path = endpoint_path(&client, "Jobs");
+    let next_path = path.clone();
+    server.route(Method::GET, &path, move |request| {
+        let offset = query_number(request, "offset");
+        let limit = query_number(request, "limit");
+        let results: Vec<usize> = (offset..(offset + limit).min(7)).collect();
+        let mut body = json!({ "offset": offset, "limit": limit, "results": results });
+        if offset + limit < 7 {
+            body["_links"] = json!({ "next": { "href": format!("{}?offset={}&limit={}", next_path, offset + limit, limit) } });
+        }
+        MockResponse::json(StatusCode::OK, body)
+    });
+
+    let mut paginator = client.paginate(&client.url("Jobs").unwrap());
+    paginator.page_size(3);
+    assert_eq!(paginator.total().await.unwrap(), None);
+
+    let items: Vec<usize> = paginator.items::<usize>().try_collect().await.unwrap();
+    assert_eq!(items, (0..7).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn test_mock_paginate_entman() {
+    let server = MockServer::builder(VProfile::ENTMAN).start().await;
+    let client = builder(&server)
+        .build_client(server.profile())
+        .await
+        .unwrap();
+    server.route(Method::GET, &endpoint_path(&client, "query"), |request| {
+        let page = query_number(request, "page");
+        let page_size = query_number(request, "pageSize");
+        let start = (page - 1) * page_size;
+        let refs: Vec<Value> = (start..(start + page_size).min(7))
+            .map(|i| json!({ "Name": format!("job-{}", i) }))
+            .collect();
+        MockResponse::json(
+            StatusCode::OK,
+            json!({
+                "Refs": { "Refs": refs },
+                "PagingInfo": { "PageNum": page, "PageSize": page_size, "PagesCount": 7usize.div_ceil(page_size) }
+            }),
+        )
+    });
+
+    let mut paginator = client.paginate(&client.url("query?type=Job&format=Refs").unwrap());
+    paginator.page_size(3);
+    let names: Vec<String> = paginator
+        .items::<Value>()
+        .map_ok(|item| item["Name"].as_str().unwrap().to_string())
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(names.len(), 7);
+    assert_eq!(names[6], "job-6");
+
+    paginator.items_pointer("/Entities/Jobs/Jobs");
+    let result: Result<Vec<Value>, _> = paginator.items::<Value>().try_collect().await;
+    assert!(matches!(result, Err(LogInError::PaginationError(_))));
+}
+
+/// Routes a session resource which returns each response in turn, then repeats the last one.
+fn session_route(server: &MockServer, path: &str, responses: Vec<Value>) {
+    let polls = AtomicUsize::new(0);
+    server.route(Method::GET, path, move |_| {
+        let poll = polls.fetch_add(1, Ordering::Relaxed);
+        MockResponse::json(
+            StatusCode::OK,
+            responses[poll.min(responses.len() - 1)].clone(),
+        )
+    });
+}
+
+#[tokio::test]
+async fn test_mock_poll_sessions() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let client = builder(&server)
+        .build_client(server.profile())
+        .await
+        .unwrap();
+    session_route(
+        &server,
+        &endpoint_path(&client, "sessions/1"),
+        vec![
+            json!({ "state": "Starting", "progressPercent": 0, "result": { "result": "None" } }),
+            json!({ "state": "Working", "progressPercent": 50, "result": { "result": "None" } }),
+            json!({ "state": "Stopped", "progressPercent": 100, "result": { "result": "Warning", "message": "1 VM skipped", "isCanceled": false } }),
+        ],
+    );
+
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let seen = progress.clone();
+    let status = client
+        .poll(&client.url("sessions/1").unwrap())
+        .interval(Duration::from_millis(10))
+        .on_progress(move |status| seen.lock().unwrap().push(status.progress.unwrap()))
+        .wait()
+        .await
+        .unwrap();
+    assert_eq!(status.result, Some(SessionResult::Warning));
+    assert_eq!(status.message.as_deref(), Some("1 VM skipped"));
+    assert_eq!(*progress.lock().unwrap(), [0, 50, 100]);
+
+    let server = MockServer::builder(VProfile::VB365).start().await;
+    let client = builder(&server)
+        .build_client(server.profile())
+        .await
+        .unwrap();
+    session_route(
+        &server,
+        &endpoint_path(&client, "RestoreSessions/1"),
+        vec![
+            json!({ "status": "Running" }),
+            json!({ "status": "Failed" }),
+        ],
+    );
+    let status = client
+        .poll(&client.url("RestoreSessions/1").unwrap())
+        .interval(Duration::from_millis(10))
+        .wait()
+        .await
+        .unwrap();
+    assert_eq!(status.result, Some(SessionResult::Failed));
+
+    let server = MockServer::builder(VProfile::ENTMAN).start().await;
+    let client = builder(&server)
+        .build_client(server.profile())
+        .await
+        .unwrap();
+    session_route(
+        &server,
+        &endpoint_path(&client, "tasks/task-1"),
+        vec![
+            json!({ "TaskId": "task-1", "State": "Running" }),
+            json!({ "TaskId": "task-1", "State": "Finished", "Result": { "Success": true, "Message": "Ok" } }),
+        ],
+    );
+    let status = client
+        .poll(&client.url("tasks/task-1").unwrap())
+        .interval(Duration::from_millis(10))
+        .wait()
+        .await
+        .unwrap();
+    assert_eq!(status.result, Some(SessionResult::Success));
+    assert_eq!(status.state.as_deref(), Some("Finished"));
+}
+
+#[tokio::test]
+async fn test_mock_poll_timeout_and_cancel() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let client = builder(&server)
+        .build_client(server.profile())
+        .await
+        .unwrap();
+    session_route(
+        &server,
+        &endpoint_path(&client, "sessions/1"),
+        vec![json!({ "state": "Working", "progressPercent": 10 })],
+    );
+    let url = client.url("sessions/1").unwrap();
+
+    let result = client
+        .poll(&url)
+        .interval(Duration::from_millis(10))
+        .timeout(Duration::from_millis(50))
+        .wait()
+        .await;
+    assert!(matches!(result, Err(LogInError::PollTimeout(_))));
+
+    let mut poller = client.poll(&url);
+    poller.interval(Duration::from_secs(60));
+    let canceller = poller.canceller();
+    let wait = tokio::spawn(async move { poller.wait().await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    canceller.cancel();
+    assert!(matches!(
+        wait.await.unwrap(),
+        Err(LogInError::PollCancelled)
+    ));
+}
+
+#[tokio::test]
+async fn test_mock_poll_stops_hung_request() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let client = builder(&server)
+        .build_client(server.profile())
+        .await
+        .unwrap();
+
+    // A server which accepts connections and never responds
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/sessions/1", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut connections = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            connections.push(stream);
+        }
+    });
+
+    let result = tokio::time::timeout(
+        Duration::from_secs(5),
+        client.poll(&url).timeout(Duration::from_millis(100)).wait(),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(result, Err(LogInError::PollTimeout(_))));
+
+    let poller = client.poll(&url);
+    let canceller = poller.canceller();
+    let clone = poller.clone();
+    let wait = tokio::spawn(async move { poller.wait().await });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    canceller.cancel();
+    let result = tokio::time::timeout(Duration::from_secs(5), wait)
+        .await
+        .unwrap();
+    assert!(matches!(result.unwrap(), Err(LogInError::PollCancelled)));
+    assert!(matches!(clone.wait().await, Err(LogInError::PollCancelled)));
+}
+
+#[tokio::test]
+async fn test_mock_discover_api_version() {
+    let server = MockServer::builder(VProfile::VBR)
+        .server_version("12.1.2.172")
+        .start()
+        .await;
+    server.route(Method::GET, "/api/v1/jobs", |_| {
+        MockResponse::json(StatusCode::OK, json!({ "data": [] }))
+    });
+
+    let mut profile = server.profile();
+    builder(&server)
+        .discover_api_version()
+        .build(&mut profile)
+        .await
+        .unwrap();
+    assert_eq!(profile.x_api_version.as_deref(), Some("1.1-rev2"));
+
+    let client = builder(&server)
+        .discover_api_version()
+        .build_client(server.profile())
+        .await
+        .unwrap();
+    assert_eq!(client.profile().x_api_version.as_deref(), Some("1.1-rev2"));
+    let response = client
+        .send(client.client().get(client.url("jobs").unwrap()))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        server.requests().last().unwrap().headers["X-Api-Version"],
+        "1.1-rev2"
+    );
+
+    // A manually set version is used without asking the server
+    let server_info_requests = || {
+        server
+            .requests()
+            .iter()
+            .filter(|request| request.path == "/api/v1/serverInfo")
+            .count()
+    };
+    let before = server_info_requests();
+    let mut profile = server.profile();
+    builder(&server)
+        .discover_api_version()
+        .x_api_version("1.1-rev0".to_string())
+        .build(&mut profile)
+        .await
+        .unwrap();
+    assert_eq!(profile.x_api_version.as_deref(), Some("1.1-rev0"));
+    assert_eq!(server_info_requests(), before);
+}
+
+#[tokio::test]
+async fn test_mock_unsupported_server_version() {
+    let server = MockServer::builder(VProfile::VB365)
+        .server_version("6.0.0.367")
+        .start()
+        .await;
+
+    let mut profile = server.profile();
+    let result = builder(&server)
+        .discover_api_version()
+        .build(&mut profile)
+        .await;
+    assert!(matches!(
+        result,
+        Err(LogInError::UnsupportedServerVersion { ref version, ref minimum, .. })
+            if version == "6.0.0.367" && minimum == "7.0"
+    ));
+    // The profile is only changed once the version has been negotiated
+    assert_eq!(profile.api_version, server.profile().api_version);
+
+    let server = MockServer::builder(VProfile::VB365)
+        .server_version("8.1.0.305")
+        .start()
+        .await;
+    let mut profile = server.profile();
+    builder(&server)
+        .discover_api_version()
+        .build(&mut profile)
+        .await
+        .unwrap();
+    assert_eq!(profile.api_version, "v8");
+    assert!(server
+        .requests()
+        .iter()
+        .any(|request| request.path == "/v7/ServiceInstance"));
+}
+
+#[cfg(feature = "toml")]
+#[tokio::test]
+async fn test_mock_context_file() {
+    use vauth::ContextFile;
+
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let dir = std::env::temp_dir().join(format!("vauth-mock-contexts-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("ca.pem"), server.ca_certificate_pem()).unwrap();
+    std::fs::write(dir.join("password"), server.password()).unwrap();
+
+    let config = dir.join("config.toml");
+    std::fs::write(
+        &config,
+        format!(
+            r#"
+current_context = "lab"
+
+[contexts.lab]
+address = "{}"
+profile = "vbr-lab"
+username = "{}"
+credentials = {{ file = "password" }}
+token_cache = "tokens.json"
+tls = {{ root_certificates = ["ca.pem"], disable_system_roots = true }}
+
+[profiles.vbr-lab]
+extends = "VBR"
+x_api_version = "1.2-rev1"
+"#,
+            server.address(),
+            server.username()
+        ),
+    )
+    .unwrap();
+
+    let contexts = ContextFile::load(&config).unwrap();
+    assert_eq!(contexts.current_context(), Some("lab"));
+    let (mut builder, profile) = contexts.current_builder().unwrap();
+    assert_eq!(profile.name, "vbr-lab");
+    let client = builder.build_client(profile).await.unwrap();
+    assert_eq!(server.login_count(), 1);
+    assert!(dir.join("tokens.json").exists());
+
+    drop(client);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// Runs with the default features, the only test which sets the context file variables
+#[tokio::test]
+async fn test_mock_default_context_file() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let dir = std::env::temp_dir().join(format!("vauth-mock-config-{}", std::process::id()));
+    let config_dir = dir.join("vauth");
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(config_dir.join("ca.pem"), server.ca_certificate_pem()).unwrap();
+    std::fs::write(config_dir.join("password"), server.password()).unwrap();
+
+    let config = config_dir.join("config.json");
+    std::fs::write(
+        &config,
+        json!({
+            "current_context": "lab",
+            "contexts": {
+                "lab": {
+                    "address": server.address(),
+                    "profile": "VBR",
+                    "username": server.username(),
+                    "credentials": { "file": "password" },
+                    "token_cache": "tokens.json",
+                    "tls": { "root_certificates": ["ca.pem"], "disable_system_roots": true }
+                }
+            }
+        })
+        .to_string(),
+    )
+    .unwrap();
+
+    // config.json is found in the config directory when there is no config.toml
+    std::env::remove_var("VAUTH_CONFIG");
+    std::env::set_var("XDG_CONFIG_HOME", &dir);
+    assert_eq!(vauth::ContextFile::default_path(), Some(config.clone()));
+    let (mut builder, profile) = VClientBuilder::from_current_context().unwrap();
+    let client = builder.build_client(profile).await.unwrap();
+    assert_eq!(server.login_count(), 1);
+    assert!(config_dir.join("tokens.json").exists());
+
+    // The default file is read from VAUTH_CONFIG, the saved token is reused
+    std::env::set_var("VAUTH_CONFIG", &config);
+    let (mut builder, profile) = VClientBuilder::from_context("lab").unwrap();
+    builder.build_client(profile).await.unwrap();
+    assert_eq!(server.login_count(), 1);
+    std::env::remove_var("VAUTH_CONFIG");
+    std::env::remove_var("XDG_CONFIG_HOME");
+
+    drop(client);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,85 @@
+//! Proxies from the environment, in their own test binary as the environment is shared by
+//! every test in a process.
+
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use vauth::mock::MockServer;
+use vauth::{StaticPassword, VClientBuilder, VProfile};
+
+/// A minimal HTTP CONNECT proxy, returning its address and the targets it tunnelled to.
+async fn connect_proxy() -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    let targets = Arc::new(Mutex::new(Vec::new()));
+    let tunnelled = targets.clone();
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let tunnelled = tunnelled.clone();
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+                let mut request_line = String::new();
+                stream.read_line(&mut request_line).await.unwrap();
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).await.unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                }
+
+                let target = request_line.split_whitespace().nth(1).unwrap().to_string();
+                tunnelled.lock().unwrap().push(target.clone());
+                let mut stream = stream.into_inner();
+                let mut upstream = TcpStream::connect(target).await.unwrap();
+                stream
+                    .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+                    .await
+                    .unwrap();
+                let _ = tokio::io::copy_bidirectional(&mut stream, &mut upstream).await;
+            });
+        }
+    });
+
+    (address, targets)
+}
+
+#[tokio::test]
+async fn test_no_proxy_with_environment_proxy() {
+    let server = MockServer::builder(VProfile::VBR).start().await;
+    let (proxy, targets) = connect_proxy().await;
+    for name in ["HTTP_PROXY", "ALL_PROXY", "NO_PROXY"] {
+        std::env::remove_var(name);
+        std::env::remove_var(name.to_ascii_lowercase());
+    }
+    std::env::set_var("HTTPS_PROXY", &proxy);
+
+    let builder = || {
+        let mut builder = VClientBuilder::new(server.address(), &server.username());
+        builder
+            .insecure()
+            .credentials(StaticPassword::new(&server.password()));
+        builder
+    };
+
+    // The environment proxy is used by default
+    builder().build(&mut server.profile()).await.unwrap();
+    assert_eq!(targets.lock().unwrap().len(), 1);
+
+    // and the no proxy list applies to it
+    builder()
+        .no_proxy("127.0.0.1")
+        .build(&mut server.profile())
+        .await
+        .unwrap();
+    assert_eq!(targets.lock().unwrap().len(), 1);
+
+    builder()
+        .no_proxy("vbr01.corp.local")
+        .build(&mut server.profile())
+        .await
+        .unwrap();
+    assert_eq!(targets.lock().unwrap().len(), 2);
+    assert_eq!(targets.lock().unwrap()[1], server.address());
+}